@@ -0,0 +1,105 @@
+-- Everything up to the introduction of versioned migrations. Databases that
+-- predate schema_version already have some of these tables, so every
+-- statement has to be a no-op for them.
+
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY,
+    series TEXT NOT NULL,
+    year INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+CREATE INDEX IF NOT EXISTS events_status_year ON events (status, year);
+
+CREATE TABLE IF NOT EXISTS documents (
+    id INTEGER PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES events (id),
+    title TEXT NOT NULL,
+    href TEXT NOT NULL,
+    mirror TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+CREATE INDEX IF NOT EXISTS documents_event_status
+    ON documents (event_id, status);
+
+CREATE TABLE IF NOT EXISTS images (
+    id INTEGER PRIMARY KEY,
+    document_id INTEGER NOT NULL REFERENCES documents (id),
+    url TEXT NOT NULL,
+    page_number INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+CREATE INDEX IF NOT EXISTS images_document_page
+    ON images (document_id, page_number);
+
+CREATE TABLE IF NOT EXISTS allow_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL REFERENCES events (id),
+    response TEXT NOT NULL,
+    approved_by TEXT,
+    approved_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+CREATE INDEX IF NOT EXISTS allow_requests_event ON allow_requests (event_id);
+
+CREATE TABLE IF NOT EXISTS guilds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    discord_id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    joined_at TEXT NOT NULL,
+    left_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS threads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    discord_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    event_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+CREATE INDEX IF NOT EXISTS threads_guild_event ON threads (guild_id, event_id);
+
+CREATE TABLE IF NOT EXISTS guild_series_settings (
+    guild_id INTEGER NOT NULL,
+    series TEXT NOT NULL,
+    channel_id TEXT,
+    role_id TEXT,
+    use_threads INTEGER NOT NULL DEFAULT 1,
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    PRIMARY KEY (guild_id, series)
+);
+CREATE INDEX IF NOT EXISTS guild_series_settings_subscribed
+    ON guild_series_settings (series, guild_id) WHERE channel_id IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    document_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+    channel_id TEXT NOT NULL,
+    role_id TEXT,
+    status TEXT NOT NULL,
+    message_id TEXT,
+    error TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    UNIQUE (document_id, guild_id, channel_id)
+);
+CREATE INDEX IF NOT EXISTS deliveries_document_status
+    ON deliveries (document_id, status);
+CREATE INDEX IF NOT EXISTS deliveries_status_next_attempt
+    ON deliveries (status, next_attempt_at);
+
+CREATE TABLE IF NOT EXISTS destination_health (
+    guild_id INTEGER NOT NULL,
+    series TEXT NOT NULL,
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    disabled_at TEXT,
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    PRIMARY KEY (guild_id, series)
+);
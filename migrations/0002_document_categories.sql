@@ -0,0 +1,6 @@
+-- Left NULL here, the runner classifies documents from their title with
+-- `DocumentCategory::classify` the first time it comes across them.
+ALTER TABLE documents ADD COLUMN category TEXT;
+
+-- Comma separated category keys the destination gets, NULL for all of them.
+ALTER TABLE guild_series_settings ADD COLUMN categories TEXT;
@@ -0,0 +1,109 @@
+use serenity::all::{
+    CacheHttp, CommandInteraction, CommandOptionType, CreateCommand,
+    CreateCommandOption, CreateEmbed, EditInteractionResponse, Permissions,
+    ResolvedOption, ResolvedValue,
+};
+
+use crate::{error::Result, storage::Storage};
+
+const DEAD_LETTER_LIMIT: u32 = 15;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("deliveries")
+        .description("Inspect and replay failed deliveries")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .set_options(vec![
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "dead-letters",
+                "List deliveries that ran out of retries",
+            ),
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "replay",
+                "Queue dead letters for delivery again",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "id",
+                    "Delivery to replay, replays all dead letters if omitted",
+                )
+                .required(false),
+            ),
+        ])
+}
+
+pub async fn run(
+    storage: &dyn Storage,
+    http: &impl CacheHttp,
+    cmd: CommandInteraction,
+) -> Result {
+    cmd.defer_ephemeral(http).await?;
+
+    let embed = match cmd.data.options().into_iter().next() {
+        Some(ResolvedOption {
+            name: "dead-letters",
+            ..
+        }) => dead_letters_embed(storage).await?,
+        Some(ResolvedOption {
+            name: "replay",
+            value: ResolvedValue::SubCommand(options),
+            ..
+        }) => {
+            let id = options.into_iter().find_map(|option| match option {
+                ResolvedOption {
+                    name: "id",
+                    value: ResolvedValue::Integer(id),
+                    ..
+                } => Some(id),
+                _ => None,
+            });
+            let replayed = storage.replay_dead_letters(id).await?;
+            CreateEmbed::new()
+                .title("Replay")
+                .description(format!("Queued {replayed} dead letter(s) again."))
+        },
+        _ => {
+            CreateEmbed::new().title("Error").description("Unknown subcommand.")
+        },
+    };
+
+    cmd.edit_response(http, EditInteractionResponse::new().embed(embed))
+        .await?;
+    Ok(())
+}
+
+async fn dead_letters_embed(storage: &dyn Storage) -> Result<CreateEmbed> {
+    let dead_letters = storage.fetch_dead_letters(DEAD_LETTER_LIMIT).await?;
+    if dead_letters.is_empty() {
+        return Ok(CreateEmbed::new()
+            .title("Dead Letters")
+            .description("No dead letters, all good."));
+    }
+
+    let description = dead_letters
+        .iter()
+        .map(|delivery| {
+            let error: String = delivery
+                .error
+                .as_deref()
+                .unwrap_or_default()
+                .chars()
+                .take(120)
+                .collect();
+            format!(
+                "`#{}` document `{}` guild `{}` in <#{}> after {} attempts\n```{}```",
+                delivery.id,
+                delivery.document_id,
+                delivery.guild_id,
+                delivery.channel_id,
+                delivery.attempts,
+                error
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(CreateEmbed::new().title("Dead Letters").description(description))
+}
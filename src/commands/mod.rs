@@ -1,3 +1,4 @@
+pub mod deliveries;
 pub mod repost;
 pub mod set;
 pub mod shutdown;
@@ -1,15 +1,13 @@
-use libsql::Connection;
 use serenity::all::{
     CacheHttp, CommandInteraction, CreateCommand, CreateEmbed,
     CreateInteractionResponseMessage, EditInteractionResponse, Permissions,
 };
 
 use crate::{
-    database::{
-        create_new_thread, fetch_guild_by_discord_id,
-        fetch_latest_event_by_series, fetch_thread_for_guild_and_event,
-    },
-    error::Result,
+    error::{Error, Result},
+    gateway::Gateway,
+    runner::create_new_thread,
+    storage::Storage,
 };
 
 pub fn register() -> CreateCommand {
@@ -20,11 +18,11 @@ pub fn register() -> CreateCommand {
 }
 
 pub async fn run(
-    conn: &Connection,
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
     http: &impl CacheHttp,
     cmd: CommandInteraction,
 ) -> Result {
-    _ = conn;
     let Some(guild_id) = cmd.guild_id else {
         cmd.create_response(
             http,
@@ -38,36 +36,21 @@ pub async fn run(
     };
     cmd.defer_ephemeral(http).await?;
 
-    let Some(ev) =
-        fetch_latest_event_by_series(conn, f1_bot_types::Series::F1).await?
-    else {
-        cmd.edit_response(
-            http,
-            EditInteractionResponse::new().embed(
-                CreateEmbed::new()
-                    .title("Error")
-                    .description("No Event found."),
-            ),
-        )
-        .await?;
-        return Ok(());
-    };
-    let Some(guild) = fetch_guild_by_discord_id(conn, guild_id).await? else {
-        cmd.edit_response(
-            http,
-            EditInteractionResponse::new().embed(
-                CreateEmbed::new().title("Error").description("Invalid Guild"),
-            ),
-        )
-        .await?;
-        return Ok(());
-    };
+    let ev = storage
+        .fetch_latest_event_by_series(f1_bot_types::Series::F1)
+        .await?
+        .ok_or(Error::NoSuch("event"))?;
+    let guild = storage
+        .fetch_guild_by_discord_id(&guild_id.to_string())
+        .await?
+        .ok_or(Error::NotFound("settings for this server"))?;
 
-    if fetch_thread_for_guild_and_event(conn, guild.id, ev.id as i64)
+    if storage
+        .fetch_thread_for_guild_and_event(guild.id, ev.id as i64)
         .await?
         .is_none()
     {
-        create_new_thread(conn, http, &guild, &ev).await?;
+        create_new_thread(storage, gateway, &guild, &ev).await?;
         cmd.edit_response(
             http,
             EditInteractionResponse::new().embed(
@@ -1,9 +1,15 @@
-use f1_bot_types::Series;
+use std::num::NonZeroU64;
+
+use chrono::Utc;
+use f1_bot_types::{Document, DocumentStatus, EventStatus, Series};
 use serenity::{
     all::{
-        ChannelType, CommandInteraction,
-        CommandOptionType::{Channel, SubCommand},
-        PartialChannel, ResolvedOption, ResolvedValue, Role,
+        ButtonStyle, ChannelId, ChannelType, CommandInteraction,
+        CommandOptionType::{self, Channel, SubCommand},
+        ComponentInteraction, ComponentInteractionDataKind, CreateActionRow,
+        CreateButton, CreateSelectMenu, CreateSelectMenuKind,
+        CreateSelectMenuOption, GuildId, PartialChannel, ResolvedOption,
+        ResolvedValue, Role, RoleId,
     },
     builder::{
         CreateCommand, CreateCommandOption, CreateEmbed,
@@ -14,29 +20,115 @@ use serenity::{
     prelude::Context,
 };
 
-use libsql::{Connection, params};
+use crate::{
+    config,
+    database::create_message,
+    error::{Error, Result},
+    gateway::Gateway,
+    model::{
+        category::DocumentCategory,
+        guild::{Guild, series_from_key, series_key},
+    },
+    runner::{
+        guild_cache::GuildCache, scheduler::Scheduler, with_role_mention,
+    },
+    storage::Storage,
+};
+
+/// Where the sample document of `/settings test` links to.
+const SAMPLE_DOCUMENT_URL: &str = "https://www.fia.com/documents";
+
+/// Every series a guild can get documents for, in the order they're shown.
+const SERIES: [Series; 4] =
+    [Series::F1, Series::F2, Series::F3, Series::F1Academy];
 
 pub fn register() -> CreateCommand {
     CreateCommand::new("settings")
         .description("Set up the FIA Documents Bot")
         .default_member_permissions(Permissions::ADMINISTRATOR)
         .dm_permission(false)
-        .set_options(vec![
-            create_option(Series::F1),
-            create_option(Series::F2),
-            create_option(Series::F3),
-        ])
+        .set_options(
+            SERIES
+                .into_iter()
+                .map(create_option)
+                .chain([
+                    CreateCommandOption::new(
+                        SubCommand,
+                        "show",
+                        "Show the current settings of this server",
+                    ),
+                    create_test_option(),
+                    create_categories_option(),
+                    create_clear_option(),
+                ])
+                .collect(),
+        )
+}
+
+/// Name of the `/settings` subcommand for the series.
+pub fn subcommand_name(series: Series) -> &'static str {
+    match series {
+        Series::F1 => "f1",
+        Series::F2 => "f2",
+        Series::F3 => "f3",
+        Series::F1Academy => "f1academy",
+    }
 }
 
 fn create_option(series: Series) -> CreateCommandOption {
     CreateCommandOption::new(
         SubCommand,
-        series.to_string().to_lowercase(),
+        subcommand_name(series),
         "Settings for the series",
     )
     .add_sub_option(create_thread_option())
     .add_sub_option(create_channel_option())
     .add_sub_option(create_role_option())
+    .add_sub_option(create_clear_role_option())
+}
+
+/// Required `series` option for the subcommands that aren't per series.
+fn create_series_option(description: &str) -> CreateCommandOption {
+    SERIES.into_iter().fold(
+        CreateCommandOption::new(
+            CommandOptionType::String,
+            "series",
+            description,
+        )
+        .required(true),
+        |option, series| {
+            option.add_string_choice(series.to_string(), series_key(series))
+        },
+    )
+}
+
+fn create_test_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        SubCommand,
+        "test",
+        "Send a sample document to check the setup",
+    )
+    .add_sub_option(create_series_option(
+        "Series whose destination gets the sample",
+    ))
+}
+
+fn create_categories_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        SubCommand,
+        "categories",
+        "Pick which kinds of documents get posted",
+    )
+    .add_sub_option(create_series_option("Series to pick the categories for"))
+}
+
+fn create_clear_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        SubCommand,
+        "clear",
+        "Stop posting the documents of a series",
+    )
+    .add_sub_option(create_series_option("Series to clear the settings of"))
 }
 
 fn create_channel_option() -> CreateCommandOption {
@@ -47,21 +139,29 @@ fn create_channel_option() -> CreateCommandOption {
 
 fn create_thread_option() -> CreateCommandOption {
     CreateCommandOption::new(
-        serenity::all::CommandOptionType::Boolean,
+        CommandOptionType::Boolean,
         "threads",
         "Whether or not to use threads (default = true)",
     )
-    .required(true)
+    .required(false)
 }
 
 fn create_role_option() -> CreateCommandOption {
     CreateCommandOption::new(
-        serenity::all::CommandOptionType::Role,
+        CommandOptionType::Role,
         "notify_role",
         "Optional Role that will be notified using @Role",
     )
 }
 
+fn create_clear_role_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::Boolean,
+        "clear_role",
+        "Stop notifying the role set before",
+    )
+}
+
 fn error_embed(
     title: &str,
     description: &str,
@@ -69,11 +169,25 @@ fn error_embed(
     CreateEmbed::new().title(title).description(description).color(0xFF0000)
 }
 
+/// Embed for outcomes and questions that aren't errors.
+fn info_embed(
+    title: &str,
+    description: &str,
+) -> CreateEmbed {
+    CreateEmbed::new()
+        .title(title)
+        .description(description)
+        .color(config::get().branding.embed_color)
+}
+
 pub async fn run(
-    pool: &Connection,
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    scheduler: &Scheduler,
+    guild_cache: &GuildCache,
     ctx: &Context,
     cmd: CommandInteraction,
-) -> crate::error::Result {
+) -> Result {
     if cmd.guild_id.is_none() {
         let builder = CreateInteractionResponse::Message(
             CreateInteractionResponseMessage::new().ephemeral(true).embed(
@@ -90,40 +204,11 @@ pub async fn run(
     cmd.defer_ephemeral(ctx).await?;
     let options = cmd.data.options();
 
-    let subcommand = options.into_iter().next();
-    if let Some(command) = subcommand {
-        if let ResolvedValue::SubCommand(options) = command.value {
-            let rv = match command.name {
-                "f1" => series_command(Series::F1, pool, &cmd, options).await,
-                "f2" => series_command(Series::F2, pool, &cmd, options).await,
-                "f3" => series_command(Series::F3, pool, &cmd, options).await,
-                _ => {
-                    let builder = CreateInteractionResponseFollowup::new()
-                        .ephemeral(true)
-                        .embed(error_embed(
-                            "Error",
-                            "Error invalid series selected.",
-                        ));
-                    cmd.create_followup(ctx, builder).await?;
-                    return Ok(());
-                },
-            };
-            match rv {
-                Err(why) => {
-                    let builder = CreateInteractionResponseFollowup::new()
-                        .embed(error_embed("Error", &why));
-                    cmd.create_followup(ctx, builder).await?;
-                    return Ok(());
-                },
-                Ok(s) => {
-                    let builder = CreateInteractionResponseFollowup::new()
-                        .embed(error_embed("Success", &s));
-                    cmd.create_followup(ctx, builder).await?;
-                    return Ok(());
-                },
-            };
-        }
-    } else {
+    let subcommand = options.into_iter().next().and_then(|f| match f.value {
+        ResolvedValue::SubCommand(options) => Some((f.name, options)),
+        _ => None,
+    });
+    let Some((name, options)) = subcommand else {
         let builder = CreateInteractionResponseFollowup::new()
             .ephemeral(true)
             .embed(error_embed(
@@ -131,122 +216,835 @@ pub async fn run(
                 "There was an error parsing your command data.",
             ));
         cmd.create_followup(ctx, builder).await?;
+        return Ok(());
+    };
+    if name == "show" {
+        let guild_id = cmd.guild_id.unwrap();
+        let embed = show_embed(storage, ctx, guild_id).await?;
+        let builder = CreateInteractionResponseFollowup::new().embed(embed);
+        cmd.create_followup(ctx, builder).await?;
+        return Ok(());
+    }
+    if name == "test" {
+        let series = selected_series(options)?;
+        let guild_id = cmd.guild_id.unwrap();
+        let embed =
+            test_embed(storage, gateway, scheduler, guild_id, series).await?;
+        let builder = CreateInteractionResponseFollowup::new().embed(embed);
+        cmd.create_followup(ctx, builder).await?;
+        return Ok(());
+    }
+    if name == "categories" {
+        let series = selected_series(options)?;
+        let guild_id = cmd.guild_id.unwrap();
+        let builder = categories_menu(storage, guild_id, series).await?;
+        cmd.create_followup(ctx, builder).await?;
+        return Ok(());
+    }
+    if name == "clear" {
+        let series = selected_series(options)?;
+        let builder = CreateInteractionResponseFollowup::new()
+            .embed(info_embed(
+                "Clear settings?",
+                &format!(
+                    "This stops posting {series} documents in this \
+                    server and forgets the channel, notify role, \
+                    thread setting and categories for them."
+                ),
+            ))
+            .components(vec![CreateActionRow::Buttons(vec![
+                CreateButton::new(format!("clear-{}", series_key(series)))
+                    .label("Clear")
+                    .style(ButtonStyle::Danger),
+            ])]);
+        cmd.create_followup(ctx, builder).await?;
+        return Ok(());
     }
+    let Some(series) = series_from_key(name) else {
+        let builder = CreateInteractionResponseFollowup::new()
+            .ephemeral(true)
+            .embed(error_embed("Error", "Error invalid series selected."));
+        cmd.create_followup(ctx, builder).await?;
+        return Ok(());
+    };
+    let rv =
+        series_command(series, storage, guild_cache, ctx, &cmd, options).await;
+    let builder = match rv {
+        Err(why) => {
+            if why.is_reportable() {
+                sentry::capture_error(&why);
+            }
+            CreateInteractionResponseFollowup::new()
+                .embed(error_embed("Error", &why.user_message()))
+        },
+        Ok(builder) => builder,
+    };
+    cmd.create_followup(ctx, builder).await?;
 
     Ok(())
 }
 
+/// The series picked in the `series` option.
+fn selected_series(options: Vec<ResolvedOption<'_>>) -> Result<Series> {
+    options
+        .into_iter()
+        .find_map(|f| match (f.name, f.value) {
+            ("series", ResolvedValue::String(key)) => series_from_key(key),
+            _ => None,
+        })
+        .ok_or_else(|| Error::InvalidSettings("no series selected".to_owned()))
+}
+
+/// Settings for a series as they are after `/settings`, with whatever
+/// wasn't given taken from what was saved before.
+#[derive(Clone, Copy)]
+struct SettingsUpdate {
+    series: Series,
+    channel: u64,
+    threads: bool,
+    role: Option<u64>,
+}
+
+impl SettingsUpdate {
+    /// Id of the "save anyway" button, which has to carry the whole update
+    /// since nothing is stored until it's clicked.
+    fn custom_id(&self) -> String {
+        format!(
+            "save-{}:{}:{}:{}",
+            series_key(self.series),
+            self.channel,
+            u8::from(self.threads),
+            self.role.unwrap_or_default()
+        )
+    }
+
+    fn from_custom_id(data: &str) -> Option<Self> {
+        let mut parts = data.split(':');
+        let series = series_from_key(parts.next()?)?;
+        let channel: u64 = parts.next()?.parse().ok()?;
+        let threads = parts.next()? == "1";
+        let role: u64 = parts.next()?.parse().ok()?;
+        Some(Self {
+            series,
+            channel,
+            threads,
+            role: (role != 0).then_some(role),
+        })
+    }
+}
+
 async fn series_command(
     series: Series,
-    pool: &Connection,
+    storage: &dyn Storage,
+    guild_cache: &GuildCache,
+    ctx: &Context,
     cmd: &CommandInteraction,
     options: Vec<ResolvedOption<'_>>,
-) -> Result<String, String> {
+) -> Result<CreateInteractionResponseFollowup> {
     let options = resolve_options(options);
-    if options.is_none() {
-        return Err("Failed to resolve command options.".to_owned());
-    }
-    let (channel, threads, role) = options.unwrap();
     let guild = cmd.guild_id.unwrap();
-    let role_id = role.map(|role| role.id.get());
+    let saved = storage
+        .fetch_guild_by_discord_id(&guild.to_string())
+        .await?
+        .ok_or(Error::NotFound("settings for this server"))?;
+    let (role, channel, threads) = saved.settings_for_series(series);
+    let channel = options
+        .channel
+        .map(|f| f.id.get())
+        .or_else(|| channel.and_then(|f| f.parse().ok()));
+    let Some(channel) = channel else {
+        return Err(Error::InvalidSettings(format!(
+            "no channel set for {series}, pick one with the `channel` option"
+        )));
+    };
+    // Left out options keep what was saved, the role can only be dropped
+    // explicitly.
+    let role = match (options.role, options.clear_role) {
+        (Some(_), true) => {
+            return Err(Error::InvalidSettings(
+                "pick either `notify_role` or `clear_role`".to_owned(),
+            ));
+        },
+        (Some(role), false) => Some(role.id.get()),
+        (None, true) => None,
+        (None, false) => role.and_then(|f| f.parse().ok()),
+    };
+    let update = SettingsUpdate {
+        series,
+        channel,
+        threads: options.threads.unwrap_or(threads),
+        role,
+    };
 
-    let channel_id = channel.map(|channel| channel.id.get());
+    let channel_id =
+        NonZeroU64::new(channel).map(ChannelId::from).ok_or_else(|| {
+            Error::InvalidSettings("the channel id can't be 0".to_owned())
+        })?;
+    let required = required_permissions(
+        update.threads,
+        role_needs_mention_everyone(ctx, guild, update.role),
+    );
+    let missing = missing_permissions(ctx, guild, channel_id, required);
+    if let Some(missing) = missing.filter(|f| !f.is_empty()) {
+        let missing: Vec<_> = missing
+            .get_permission_names()
+            .into_iter()
+            .map(|f| format!("- {f}"))
+            .collect();
+        return Ok(CreateInteractionResponseFollowup::new()
+            .embed(error_embed(
+                "Missing permissions",
+                &format!(
+                    "The bot is missing these permissions in \
+                        <#{channel}>:\n{}\n\n\
+                        Documents won't arrive until they are granted. Fix \
+                        the channel permissions and run the command again, or \
+                        save the settings anyway.",
+                    missing.join("\n")
+                ),
+            ))
+            .components(vec![CreateActionRow::Buttons(vec![
+                CreateButton::new(update.custom_id())
+                    .label("Save anyway")
+                    .style(ButtonStyle::Danger),
+            ])]));
+    }
 
-    match series_query(pool, series, channel_id, threads, role_id, guild.get())
-        .await
+    let message = save_settings(storage, guild_cache, guild, &update).await?;
+    Ok(CreateInteractionResponseFollowup::new()
+        .embed(info_embed("Success", &message)))
+}
+
+/// Saves the settings the permission check complained about, from the
+/// "save anyway" button.
+pub async fn save_anyway(
+    storage: &dyn Storage,
+    guild_cache: &GuildCache,
+    ctx: &Context,
+    cmd: ComponentInteraction,
+    data: &str,
+) -> Result {
+    let (Some(guild), Some(update)) =
+        (cmd.guild_id, SettingsUpdate::from_custom_id(data))
+    else {
+        return Ok(());
+    };
+    let message = save_settings(storage, guild_cache, guild, &update).await?;
+    cmd.create_response(
+        ctx,
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .embed(info_embed("Success", &message))
+                .components(vec![]),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Clears the settings of the series once the admin confirmed it with the
+/// button `/settings clear` shows.
+pub async fn clear_series(
+    storage: &dyn Storage,
+    guild_cache: &GuildCache,
+    ctx: &Context,
+    cmd: ComponentInteraction,
+    key: &str,
+) -> Result {
+    let (Some(guild_id), Some(series)) = (cmd.guild_id, series_from_key(key))
+    else {
+        return Ok(());
+    };
+    storage
+        .upsert_series_settings(&guild_id.to_string(), series, None, None, true)
+        .await?;
+    storage
+        .update_series_categories(&guild_id.to_string(), series, None)
+        .await?;
+    guild_cache.invalidate().await;
+    if let Some(guild) =
+        storage.fetch_guild_by_discord_id(&guild_id.to_string()).await?
     {
-        Ok(_) => {
-            if role_id.is_some() && channel.is_some() {
-                Ok(format!(
-                    r#"Updated settings for {series}
-                notify_role <@&{}>
-                channel <#{}>
-                use threads: `{}`"#,
-                    role_id.unwrap(),
-                    channel.unwrap().id.get(),
-                    threads
+        storage.reset_destination_health(guild.id, series).await?;
+    }
+    cmd.create_response(
+        ctx,
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .embed(info_embed(
+                    "Success",
+                    &format!(
+                        "Cleared the settings for {series}, its documents \
+                        won't be posted anymore."
+                    ),
+                ))
+                .components(vec![]),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Select menu of the categories with the ones the series destination gets
+/// right now already picked.
+async fn categories_menu(
+    storage: &dyn Storage,
+    guild_id: GuildId,
+    series: Series,
+) -> Result<CreateInteractionResponseFollowup> {
+    let guild = storage
+        .fetch_guild_by_discord_id(&guild_id.to_string())
+        .await?
+        .ok_or(Error::NotFound("settings for this server"))?;
+    let selected = guild.categories_for_series(series);
+    let options = DocumentCategory::ALL
+        .into_iter()
+        .map(|category| {
+            CreateSelectMenuOption::new(category.label(), category.key())
+                .default_selection(
+                    selected.is_none_or(|f| f.contains(&category)),
+                )
+        })
+        .collect();
+    let menu = CreateSelectMenu::new(
+        format!("categories-{}", series_key(series)),
+        CreateSelectMenuKind::String {
+            options,
+        },
+    )
+    .min_values(1)
+    .max_values(DocumentCategory::ALL.len() as u8);
+    Ok(CreateInteractionResponseFollowup::new()
+        .embed(
+            CreateEmbed::new()
+                .title(format!("Categories for {series}"))
+                .description(
+                    "Only documents of the picked categories get posted, \
+                    they are guessed from the document titles.",
+                )
+                .color(config::get().branding.embed_color),
+        )
+        .components(vec![CreateActionRow::SelectMenu(menu)]))
+}
+
+/// Saves the categories picked in the menu `/settings categories` shows.
+pub async fn update_categories(
+    storage: &dyn Storage,
+    guild_cache: &GuildCache,
+    ctx: &Context,
+    cmd: ComponentInteraction,
+    key: &str,
+) -> Result {
+    let (Some(guild_id), Some(series)) = (cmd.guild_id, series_from_key(key))
+    else {
+        return Ok(());
+    };
+    let ComponentInteractionDataKind::StringSelect {
+        values,
+    } = &cmd.data.kind
+    else {
+        return Ok(());
+    };
+    let categories: Vec<_> = DocumentCategory::ALL
+        .into_iter()
+        .filter(|f| values.iter().any(|value| value == f.key()))
+        .collect();
+    // Everything picked is stored as no filter, so categories added later
+    // get posted too.
+    let filter = (categories.len() < DocumentCategory::ALL.len())
+        .then_some(categories.as_slice());
+    storage
+        .update_series_categories(&guild_id.to_string(), series, filter)
+        .await?;
+    guild_cache.invalidate().await;
+    cmd.create_response(
+        ctx,
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .embed(info_embed(
+                    "Success",
+                    &format!(
+                        "{series} documents posted from now on: {}",
+                        categories_label(filter)
+                    ),
                 ))
-            } else if channel.is_some() {
-                return Ok(format!(
-                    r#"Updated settings for {series}
-                       channel <#{}>
-                       use threads: `{}`"#,
-                    channel.unwrap().id.get(),
-                    threads
-                ));
-            } else {
-                return Ok(
-                    "cleared channel, won't be notified anymore.".to_string()
+                .components(vec![]),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Lists the categories of a filter, `None` being all of them.
+fn categories_label(categories: Option<&[DocumentCategory]>) -> String {
+    match categories {
+        None => "all".to_owned(),
+        Some(categories) => {
+            categories.iter().map(|f| f.label()).collect::<Vec<_>>().join(", ")
+        },
+    }
+}
+
+/// Permissions the bot needs in the channel for the settings to work.
+/// `mention_everyone` is needed to ping a role that isn't mentionable.
+fn required_permissions(
+    threads: bool,
+    mention_everyone: bool,
+) -> Permissions {
+    let mut required = Permissions::VIEW_CHANNEL
+        | Permissions::SEND_MESSAGES
+        | Permissions::EMBED_LINKS;
+    if threads {
+        required |= Permissions::CREATE_PUBLIC_THREADS
+            | Permissions::SEND_MESSAGES_IN_THREADS;
+    }
+    if mention_everyone {
+        required |= Permissions::MENTION_EVERYONE;
+    }
+    required
+}
+
+/// Whether pinging the role takes `MENTION_EVERYONE`, which is only the case
+/// for roles that aren't mentionable. `false` if the cache doesn't know the
+/// role, the permission check can't tell then anyway.
+fn role_needs_mention_everyone(
+    ctx: &Context,
+    guild_id: GuildId,
+    role: Option<u64>,
+) -> bool {
+    let Some(role_id) = role.and_then(NonZeroU64::new).map(RoleId::from) else {
+        return false;
+    };
+    ctx.cache
+        .guild(guild_id)
+        .and_then(|guild| guild.roles.get(&role_id).map(|f| !f.mentionable))
+        .unwrap_or(false)
+}
+
+/// Which of the permissions the bot lacks in the channel according to the
+/// cache, `None` if the cache doesn't know the guild, channel or bot member.
+fn missing_permissions(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    required: Permissions,
+) -> Option<Permissions> {
+    let bot_id = ctx.cache.current_user().id;
+    let guild = ctx.cache.guild(guild_id)?;
+    let channel = guild.channels.get(&channel_id)?;
+    let member = guild.members.get(&bot_id)?;
+    Some(required.difference(guild.user_permissions_in(channel, member)))
+}
+
+/// Stores the settings and describes what was saved.
+async fn save_settings(
+    storage: &dyn Storage,
+    guild_cache: &GuildCache,
+    guild: GuildId,
+    update: &SettingsUpdate,
+) -> Result<String> {
+    let SettingsUpdate {
+        series,
+        channel,
+        threads,
+        role,
+    } = *update;
+    series_query(storage, series, Some(channel), threads, role, guild.get())
+        .await?;
+    guild_cache.invalidate().await;
+    let note = reenable_destination(storage, series, guild.get())
+        .await?
+        .unwrap_or_default();
+    let message = match role {
+        Some(role) => format!(
+            r#"Updated settings for {series}
+                notify_role <@&{role}>
+                channel <#{channel}>
+                use threads: `{threads}`"#,
+        ),
+        None => format!(
+            r#"Updated settings for {series}
+                       channel <#{channel}>
+                       use threads: `{threads}`"#,
+        ),
+    };
+    Ok(message + &note)
+}
+
+/// Renders what the guild configured for every series and how delivering
+/// there went so far.
+async fn show_embed(
+    storage: &dyn Storage,
+    ctx: &Context,
+    guild_id: GuildId,
+) -> Result<CreateEmbed> {
+    let guild = storage
+        .fetch_guild_by_discord_id(&guild_id.to_string())
+        .await?
+        .ok_or(Error::NotFound("settings for this server"))?;
+    let channels: Option<Vec<ChannelId>> = ctx
+        .cache
+        .guild(guild_id)
+        .map(|guild| guild.channels.keys().copied().collect());
+
+    let mut embed = CreateEmbed::new()
+        .title(format!("Settings for {}", guild.name))
+        .color(config::get().branding.embed_color);
+    for series in SERIES {
+        let (role, channel, threads) = guild.settings_for_series(series);
+        let Some(channel) = channel else {
+            embed = embed.field(
+                series.to_string(),
+                format!(
+                    "Not set up, use `/settings {}`.",
+                    subcommand_name(series)
+                ),
+                false,
+            );
+            continue;
+        };
+        let categories = categories_label(guild.categories_for_series(series));
+        let health = storage.fetch_destination_health(guild.id, series).await?;
+        let last_sent = storage.fetch_last_sent_at(guild.id, series).await?;
+
+        let required = required_permissions(
+            threads,
+            role_needs_mention_everyone(
+                ctx,
+                guild_id,
+                role.and_then(|f| f.parse().ok()),
+            ),
+        );
+        let reachable = match (
+            &channels,
+            channel.parse::<NonZeroU64>().map(ChannelId::from),
+        ) {
+            (_, Err(_)) => "no, invalid channel".to_owned(),
+            (Some(channels), Ok(id)) if !channels.contains(&id) => {
+                "no, the channel was deleted or the bot can't see it".to_owned()
+            },
+            // Guild not cached yet, nothing to tell.
+            (None, _) => "unknown".to_owned(),
+            (Some(_), Ok(id)) => {
+                match missing_permissions(ctx, guild_id, id, required) {
+                    Some(missing) if !missing.is_empty() => format!(
+                        "no, missing {}",
+                        missing.get_permission_names().join(", ")
+                    ),
+                    _ => "yes".to_owned(),
+                }
+            },
+        };
+        let threads = if threads {
+            "on"
+        } else {
+            "off"
+        };
+        let role = role.map_or("none".to_owned(), |f| format!("<@&{f}>"));
+        let last_sent = last_sent
+            .map_or("never".to_owned(), |f| format!("<t:{}:R>", f.timestamp()));
+        let mut value = format!(
+            "Channel: <#{channel}>\n\
+            Threads: `{threads}`\n\
+            Notify role: {role}\n\
+            Categories: {categories}\n\
+            Reachable: {reachable}\n\
+            Last delivery: {last_sent}"
+        );
+        if let Some(health) = health {
+            if let Some(disabled_at) = health.disabled_at {
+                value += &format!(
+                    "\n**Disabled** <t:{}:R>, save the settings again to \
+                    re-enable it.",
+                    disabled_at.timestamp()
                 );
             }
-        },
-        Err(why) => Err(format!("Database Error: ```log\n{why}```")),
+            if health.consecutive_failures > 0 {
+                value += &format!(
+                    "\n{} failed deliveries in a row, last error: ```{}```",
+                    health.consecutive_failures,
+                    health.last_error.unwrap_or_default()
+                );
+            }
+        }
+        embed = embed.field(series.to_string(), value, false);
     }
+    Ok(embed)
+}
+
+/// Sends a sample document to where the next document of the series would
+/// go and reports how that went.
+async fn test_embed(
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    scheduler: &Scheduler,
+    guild_id: GuildId,
+    series: Series,
+) -> Result<CreateEmbed> {
+    let guild = storage
+        .fetch_guild_by_discord_id(&guild_id.to_string())
+        .await?
+        .ok_or(Error::NotFound("settings for this server"))?;
+    Ok(match send_sample(storage, gateway, scheduler, &guild, series).await {
+        Ok(channel) => CreateEmbed::new()
+            .title("Test sent")
+            .description(format!(
+                "Sent a sample {series} document to <#{channel}>. Documents \
+                show up there, or in a thread per event if threads are on."
+            ))
+            .color(config::get().branding.embed_color),
+        Err(why) => error_embed(
+            "Test failed",
+            &format!("{}\n\nError: ```{why}```", why.user_message()),
+        ),
+    })
+}
+
+/// Delivers a made up document the way the runner would, returns the
+/// channel or thread it went to. Never opens a thread, the sample only goes
+/// into one if the running event already has it.
+async fn send_sample(
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    scheduler: &Scheduler,
+    guild: &Guild,
+    series: Series,
+) -> Result<String> {
+    let (role, channel, threads) = guild.settings_for_series(series);
+    let Some(channel) = channel else {
+        return Err(Error::InvalidSettings(format!(
+            "no channel set for {series}, use `/settings {}` first",
+            subcommand_name(series)
+        )));
+    };
+    // Events waiting for approval or denied never get documents posted.
+    let event =
+        storage.fetch_latest_event_by_series(series).await?.filter(|f| {
+            matches!(f.status, EventStatus::Allowed | EventStatus::Posted)
+        });
+    let thread = match &event {
+        Some(event) if threads => {
+            storage
+                .fetch_thread_for_guild_and_event(guild.id, event.id as i64)
+                .await?
+        },
+        _ => None,
+    };
+    let destination = thread.map_or_else(|| channel.clone(), |f| f.discord_id);
+
+    let document = Document {
+        id: 0,
+        event_id: event.map_or(0, |f| f.id as i64),
+        title: format!("Test Document - {series}"),
+        href: SAMPLE_DOCUMENT_URL.to_owned(),
+        mirror: SAMPLE_DOCUMENT_URL.to_owned(),
+        created_at: Utc::now(),
+        status: DocumentStatus::ReadyToPost,
+    };
+    let category = DocumentCategory::classify(&document.title);
+    let message = with_role_mention(
+        create_message(&document, category, vec![]),
+        role.map(String::as_str),
+    );
+    let channel_id = ChannelId::from(destination.parse::<NonZeroU64>()?);
+    let _permit = scheduler.acquire(gateway, Some(channel_id)).await;
+    gateway.send_message(channel_id, message).await?;
+    Ok(destination)
+}
+
+/// Re-enables the series destination after the settings were saved and
+/// describes why it had been disabled, if it was.
+async fn reenable_destination(
+    storage: &dyn Storage,
+    series: Series,
+    guild: u64,
+) -> Result<Option<String>> {
+    let Some(guild) =
+        storage.fetch_guild_by_discord_id(&guild.to_string()).await?
+    else {
+        return Ok(None);
+    };
+    let health = storage.fetch_destination_health(guild.id, series).await?;
+    storage.reset_destination_health(guild.id, series).await?;
+    Ok(health.and_then(|health| {
+        health.disabled_at.map(|disabled_at| {
+            format!(
+                "\n\nThis destination was disabled <t:{}:R> after {} failed \
+                attempts: ```{}```\nSaving the settings enabled it again.",
+                disabled_at.timestamp(),
+                health.consecutive_failures,
+                health.last_error.unwrap_or_default()
+            )
+        })
+    }))
 }
 
 async fn series_query(
-    pool: &Connection,
+    storage: &dyn Storage,
     series: Series,
     channel: Option<u64>,
     threads: bool,
     role: Option<u64>,
     guild: u64,
-) -> Result<u64, libsql::Error> {
+) -> Result<u64> {
     let channel = channel.map(|f| f.to_string());
     let role = role.map(|f| f.to_string());
-    let threads = if threads {
-        1
-    } else {
-        0
-    };
-    match series {
-        Series::F1 => {
-            pool.execute("UPDATE guilds SET f1_channel = ?, f1_threads = ?, f1_role = ? where discord_id = ?",
-            params![channel, threads, role, guild]).await
-        },
-        Series::F2 => {
-            pool.execute("UPDATE guilds SET f2_channel = ?, f2_threads = ?, f2_role = ? where discord_id = ?",
-            params![channel, threads, role, guild]).await
-        },
-        Series::F3 => {
-            pool.execute("UPDATE guilds SET f3_channel = ?, f3_threads = ?, f3_role = ? where discord_id = ?",
-            params![channel, threads, role, guild]).await
-        },
-        _ => panic!("F1Academy not Supported!")
-    }
-}
-
-fn resolve_options(
-    options: Vec<ResolvedOption<'_>>
-) -> Option<(Option<&PartialChannel>, bool, Option<&Role>)> {
-    let mut it = options.into_iter();
-    let threads = it.next();
-    let channel = it.next();
-    let role = it.next();
-    if let (channel, Some(threads), role) = (channel, threads, role) {
-        let channel = match channel {
-            None => None,
-            Some(data) => {
-                if let ResolvedValue::Channel(channel) = data.value {
-                    Some(channel)
-                } else {
-                    None
-                }
+    storage
+        .upsert_series_settings(
+            &guild.to_string(),
+            series,
+            channel.as_deref(),
+            role.as_deref(),
+            threads,
+        )
+        .await
+}
+
+/// Options given to a series subcommand, resolved by name.
+#[derive(Default)]
+struct SeriesOptions<'a> {
+    channel: Option<&'a PartialChannel>,
+    threads: Option<bool>,
+    role: Option<&'a Role>,
+    clear_role: bool,
+}
+
+fn resolve_options(options: Vec<ResolvedOption<'_>>) -> SeriesOptions<'_> {
+    let mut resolved = SeriesOptions::default();
+    for option in options {
+        match (option.name, option.value) {
+            ("channel", ResolvedValue::Channel(channel)) => {
+                resolved.channel = Some(channel);
             },
-        };
-        let role = match role {
-            None => None,
-            Some(data) => {
-                if let ResolvedValue::Role(role) = data.value {
-                    Some(role)
-                } else {
-                    None
-                }
+            ("threads", ResolvedValue::Boolean(threads)) => {
+                resolved.threads = Some(threads);
             },
-        };
-        if let ResolvedValue::Boolean(threads) = threads.value {
-            return Some((channel, threads, role));
+            ("notify_role", ResolvedValue::Role(role)) => {
+                resolved.role = Some(role);
+            },
+            ("clear_role", ResolvedValue::Boolean(clear)) => {
+                resolved.clear_role = clear;
+            },
+            _ => {},
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use serenity::all::CommandData;
+
+    use super::*;
+
+    #[test]
+    fn settings_update_survives_the_button() {
+        let updates = [
+            SettingsUpdate {
+                series: Series::F1Academy,
+                channel: 100,
+                threads: true,
+                role: Some(200),
+            },
+            SettingsUpdate {
+                series: Series::F2,
+                channel: 100,
+                threads: false,
+                role: None,
+            },
+        ];
+        for update in updates {
+            let id = update.custom_id();
+            let data = id.strip_prefix("save-").unwrap();
+            let parsed = SettingsUpdate::from_custom_id(data).unwrap();
+            assert_eq!(series_key(parsed.series), series_key(update.series));
+            assert_eq!(parsed.channel, update.channel);
+            assert_eq!(parsed.threads, update.threads);
+            assert_eq!(parsed.role, update.role);
+        }
+    }
+
+    #[test]
+    fn malformed_or_foreign_ids_are_ignored() {
+        for data in [
+            "",
+            "5",
+            "f1",
+            "f1:100:1",
+            "f1:channel:1:0",
+            "f1:100:1:role",
+            "indycar:100:1:0",
+        ] {
+            assert!(SettingsUpdate::from_custom_id(data).is_none(), "{data}");
         }
     }
-    None
+
+    fn command_data(options: serde_json::Value) -> CommandData {
+        serde_json::from_value(json!({
+            "id": "1",
+            "name": "settings",
+            "type": 1,
+            "options": [{ "name": "f1", "type": 1, "options": options }],
+            "resolved": {
+                "channels": {
+                    "100": {
+                        "id": "100",
+                        "name": "documents",
+                        "type": 0,
+                        "permissions": "0",
+                    },
+                },
+                "roles": {
+                    "200": {
+                        "id": "200",
+                        "name": "Stewards",
+                        "color": 0,
+                        "hoist": false,
+                        "icon": null,
+                        "unicode_emoji": null,
+                        "managed": false,
+                        "mentionable": true,
+                        "permissions": "0",
+                        "position": 1,
+                        "flags": 0,
+                    },
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn subcommand_options(data: &CommandData) -> Vec<ResolvedOption<'_>> {
+        match data.options().into_iter().next().map(|f| f.value) {
+            Some(ResolvedValue::SubCommand(options)) => options,
+            other => panic!("expected a subcommand, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn options_are_resolved_by_name() {
+        let data = command_data(json!([
+            { "name": "channel", "type": 7, "value": "100" },
+            { "name": "threads", "type": 5, "value": true },
+            { "name": "notify_role", "type": 8, "value": "200" },
+        ]));
+        let options = resolve_options(subcommand_options(&data));
+        assert_eq!(options.channel.map(|f| f.id.get()), Some(100));
+        assert_eq!(options.threads, Some(true));
+        assert_eq!(options.role.map(|f| f.id.get()), Some(200));
+        assert!(!options.clear_role);
+    }
+
+    #[test]
+    fn left_out_options_stay_unset() {
+        let data = command_data(json!([
+            { "name": "clear_role", "type": 5, "value": true },
+        ]));
+        let options = resolve_options(subcommand_options(&data));
+        assert!(options.channel.is_none());
+        assert_eq!(options.threads, None);
+        assert!(options.role.is_none());
+        assert!(options.clear_role);
+    }
 }
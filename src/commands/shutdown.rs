@@ -4,7 +4,7 @@ use serenity::all::{
     Permissions,
 };
 
-use crate::ShardManagerBox;
+use crate::runner::shutdown::Shutdown;
 
 pub fn register() -> CreateCommand {
     CreateCommand::new("shutdown")
@@ -25,11 +25,8 @@ pub async fn run(
         ),
     )
     .await?;
-    {
-        let lock = ctx.data.write().await;
-        if let Some(shards) = lock.get::<ShardManagerBox>() {
-            shards.shutdown_all().await;
-        }
+    if let Some(shutdown) = ctx.data.read().await.get::<Shutdown>() {
+        shutdown.request();
     }
     Ok(())
 }
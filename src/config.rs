@@ -0,0 +1,654 @@
+use std::{io::ErrorKind, net::SocketAddr, sync::OnceLock, time::Duration};
+
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use crate::error::{Error, Result};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Read when `CONFIG_PATH` isn't set, it's fine for it to not exist as long
+/// as the required keys come from the environment.
+const DEFAULT_PATH: &str = "config.toml";
+
+/// Environment variables starting with this override keys of the file,
+/// sections separated by `__`: `FIA__RUNNER__POLL_INTERVAL_SECS=30`.
+const ENV_PREFIX: &str = "FIA__";
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub discord: DiscordConfig,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub branding: BrandingConfig,
+    #[serde(default)]
+    pub runner: RunnerConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DiscordConfig {
+    /// Guild the maintenance commands are registered in.
+    pub control_guild_id: u64,
+    /// Channel new events are posted to for approval.
+    pub request_channel_id: u64,
+    /// Users pinged when an event needs approval.
+    #[serde(default)]
+    pub request_ping_users: Vec<u64>,
+    /// Roles pinged when an event needs approval.
+    #[serde(default)]
+    pub request_ping_roles: Vec<u64>,
+    /// Channel guild notices go to when neither the guild owner nor the
+    /// guild's system channel can be reached.
+    #[serde(default)]
+    pub notice_fallback_channel_id: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DatabaseMode {
+    /// Local file kept in sync with a sqld server, `DATABASE_URL` and
+    /// `DATABASE_TOKEN` have to be set.
+    RemoteReplica,
+    /// Plain local file, no server needed.
+    Local,
+    /// Gone once the bot stops, for trying things out.
+    Memory,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub mode: DatabaseMode,
+    /// Database file for `remote-replica` and `local`.
+    pub path: String,
+    /// How often the replica pulls changes from the remote database.
+    pub sync_interval_secs: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrandingConfig {
+    /// Color of the bot's own embeds and of documents in no particular
+    /// category, as `0xRRGGBB`.
+    pub embed_color: u32,
+    pub thumbnail_url: String,
+    pub category_colors: CategoryColors,
+}
+
+/// Colors of the document embeds by category, as `0xRRGGBB`.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CategoryColors {
+    pub decision: u32,
+    pub summons: u32,
+    pub infringement: u32,
+    pub classification: u32,
+    pub technical_report: u32,
+    pub entry_list: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunnerConfig {
+    /// Fallback for when nothing wakes the runner up.
+    pub poll_interval_secs: u64,
+    /// How many Discord requests the runner has in flight at once.
+    pub delivery_concurrency: usize,
+    /// How often a delivery is attempted before it ends up as a dead letter.
+    pub max_attempts: i64,
+    /// Delay before the first retry, doubled for every attempt after that.
+    pub retry_base_delay_secs: i64,
+    /// Upper bound for the delay between two attempts.
+    pub retry_max_delay_secs: i64,
+    /// Permanent failures in a row after which a destination gets disabled.
+    pub disable_after_failures: i64,
+    /// Days the settings of a guild that removed the bot are kept, so they
+    /// can be restored if the bot gets added back.
+    pub guild_retention_days: i64,
+    /// `/healthz` fails once the runner didn't finish a tick for this long.
+    pub stall_after_secs: u64,
+    /// How long a shutdown waits for in-flight deliveries before stopping
+    /// the shards anyway. Keep it below the container's stop grace period.
+    pub shutdown_deadline_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Address the ingest hook, `/metrics`, `/healthz` and `/readyz` are
+    /// served on, disabled if unset.
+    pub addr: Option<SocketAddr>,
+    /// Bearer token `POST /wake` has to come with. Without one only
+    /// requests from loopback can wake the runner.
+    pub wake_token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Errors and transactions are only sent to Sentry when this or
+    /// `SENTRY_DSN` is set.
+    pub sentry_dsn: Option<String>,
+    /// OTLP/HTTP endpoint the `tracing` spans are exported to, e.g.
+    /// `http://localhost:4318/v1/traces`. Disabled if unset.
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    /// Share of traces kept, for both Sentry and OTLP.
+    pub traces_sample_rate: f32,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            sentry_dsn: None,
+            otlp_endpoint: None,
+            service_name: env!("CARGO_PKG_NAME").to_owned(),
+            traces_sample_rate: 1.0,
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            mode: DatabaseMode::RemoteReplica,
+            path: "./local.db".to_owned(),
+            sync_interval_secs: 60,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn sync_interval(&self) -> Duration {
+        Duration::from_secs(self.sync_interval_secs)
+    }
+}
+
+impl Default for BrandingConfig {
+    fn default() -> Self {
+        Self {
+            embed_color: 0x003063,
+            thumbnail_url: "https://static.ort.dev/fiadontsueme/fia_logo.png"
+                .to_owned(),
+            category_colors: CategoryColors::default(),
+        }
+    }
+}
+
+impl Default for CategoryColors {
+    fn default() -> Self {
+        Self {
+            decision: 0xE10600,
+            summons: 0xF39C12,
+            infringement: 0x992D22,
+            classification: 0x1F8B4C,
+            technical_report: 0x206694,
+            entry_list: 0x71368A,
+        }
+    }
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 60,
+            delivery_concurrency: 10,
+            max_attempts: 6,
+            retry_base_delay_secs: 30,
+            retry_max_delay_secs: 60 * 60,
+            disable_after_failures: 3,
+            guild_retention_days: 30,
+            stall_after_secs: 15 * 60,
+            shutdown_deadline_secs: 30,
+        }
+    }
+}
+
+impl RunnerConfig {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs)
+    }
+
+    pub fn shutdown_deadline(&self) -> Duration {
+        Duration::from_secs(self.shutdown_deadline_secs)
+    }
+}
+
+impl DiscordConfig {
+    /// Mentions for the approval request message, empty if nobody is set up
+    /// to be pinged.
+    pub fn request_mentions(&self) -> String {
+        self.request_ping_users
+            .iter()
+            .map(|f| format!("<@{f}>"))
+            .chain(self.request_ping_roles.iter().map(|f| format!("<@&{f}>")))
+            .collect::<Vec<_>>()
+            .join(" & ")
+    }
+}
+
+impl Config {
+    fn validate(&self) -> Result {
+        let mut problems = vec![];
+        if self.discord.control_guild_id == 0 {
+            problems.push("discord.control_guild_id must be set");
+        }
+        if self.discord.request_channel_id == 0 {
+            problems.push("discord.request_channel_id must be set");
+        }
+        if self.discord.notice_fallback_channel_id == Some(0) {
+            problems.push("discord.notice_fallback_channel_id must not be 0");
+        }
+        if self.branding.embed_color > 0xFFFFFF {
+            problems.push("branding.embed_color must be at most 0xFFFFFF");
+        }
+        let colors = &self.branding.category_colors;
+        if [
+            colors.decision,
+            colors.summons,
+            colors.infringement,
+            colors.classification,
+            colors.technical_report,
+            colors.entry_list,
+        ]
+        .iter()
+        .any(|f| *f > 0xFFFFFF)
+        {
+            problems
+                .push("branding.category_colors must all be at most 0xFFFFFF");
+        }
+        if !self.branding.thumbnail_url.starts_with("https://")
+            && !self.branding.thumbnail_url.starts_with("http://")
+        {
+            problems.push("branding.thumbnail_url must be an http(s) URL");
+        }
+        if self.http.wake_token.as_deref().is_some_and(str::is_empty) {
+            problems.push("http.wake_token must not be empty");
+        }
+        if self.database.sync_interval_secs == 0 {
+            problems.push("database.sync_interval_secs must be at least 1");
+        }
+        if self.database.mode != DatabaseMode::Memory
+            && self.database.path.is_empty()
+        {
+            problems.push("database.path must be set");
+        }
+        if !(0.0..=1.0).contains(&self.telemetry.traces_sample_rate) {
+            problems
+                .push("telemetry.traces_sample_rate must be between 0 and 1");
+        }
+        let runner = &self.runner;
+        if runner.poll_interval_secs == 0 {
+            problems.push("runner.poll_interval_secs must be at least 1");
+        }
+        if runner.stall_after_secs <= runner.poll_interval_secs {
+            problems.push(
+                "runner.stall_after_secs must be above runner.poll_interval_secs",
+            );
+        }
+        if runner.delivery_concurrency == 0 {
+            problems.push("runner.delivery_concurrency must be at least 1");
+        }
+        if runner.max_attempts < 1 {
+            problems.push("runner.max_attempts must be at least 1");
+        }
+        if runner.retry_base_delay_secs < 1 {
+            problems.push("runner.retry_base_delay_secs must be at least 1");
+        }
+        if runner.retry_max_delay_secs < runner.retry_base_delay_secs {
+            problems.push(
+                "runner.retry_max_delay_secs must not be below \
+                runner.retry_base_delay_secs",
+            );
+        }
+        if runner.disable_after_failures < 1 {
+            problems.push("runner.disable_after_failures must be at least 1");
+        }
+        if runner.guild_retention_days < 0 {
+            problems.push("runner.guild_retention_days must not be negative");
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(problems.join(", ")))
+        }
+    }
+}
+
+/// Loads and validates the configuration, has to run before anything calls
+/// [`get`].
+pub fn init() -> Result {
+    let config = load()?;
+    CONFIG
+        .set(config)
+        .map_err(|_| Error::Config("configuration loaded twice".to_owned()))
+}
+
+/// Loads the defaults plus made up Discord ids, for tests. Safe to call from
+/// every test, only the first call does anything.
+#[cfg(test)]
+pub fn init_for_tests() {
+    CONFIG.get_or_init(|| {
+        "[discord]\ncontrol_guild_id = 1\nrequest_channel_id = 2\n"
+            .parse::<Table>()
+            .and_then(|f| Value::Table(f).try_into())
+            .expect("test configuration")
+    });
+}
+
+/// The configuration loaded by [`init`].
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("configuration not loaded")
+}
+
+fn load() -> Result<Config> {
+    let (path, required) = match std::env::var("CONFIG_PATH") {
+        Ok(path) => (path, true),
+        Err(_) => (DEFAULT_PATH.to_owned(), false),
+    };
+    let mut table = match std::fs::read_to_string(&path) {
+        Ok(content) => content
+            .parse::<Table>()
+            .map_err(|e| Error::Config(format!("{path}: {e}")))?,
+        Err(why) if why.kind() == ErrorKind::NotFound && !required => {
+            Table::new()
+        },
+        Err(why) => {
+            return Err(Error::Config(format!("reading {path}: {why}")));
+        },
+    };
+    parse(table, std::env::vars(), &path)
+}
+
+/// Applies the environment overrides to the file's table and validates the
+/// result, `path` is only used in the error messages.
+fn parse(
+    mut table: Table,
+    vars: impl Iterator<Item = (String, String)>,
+    path: &str,
+) -> Result<Config> {
+    let typed = apply_env_overrides(&mut table, vars)?;
+
+    let config: Config = Value::Table(table).try_into().map_err(|e| {
+        if typed.is_empty() {
+            return Error::Config(format!("{path}: {e}"));
+        }
+        Error::Config(format!(
+            "{path}: {e}\nread as TOML from the environment: {}, quote \
+            values meant as strings like FIA__HTTP__WAKE_TOKEN='\"123456\"'",
+            typed.join(", ")
+        ))
+    })?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Returns the variables whose values were read as something other than a
+/// string, for pointing them out if the result doesn't fit.
+fn apply_env_overrides(
+    table: &mut Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<Vec<String>> {
+    let mut typed = vec![];
+    for (key, raw) in vars {
+        let Some(path) = key.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let path: Vec<_> = path.split("__").map(str::to_lowercase).collect();
+        let Some((field, sections)) = path.split_last() else {
+            continue;
+        };
+
+        let mut current = &mut *table;
+        for section in sections {
+            let entry = current
+                .entry(section.clone())
+                .or_insert_with(|| Value::Table(Table::new()));
+            let Value::Table(next) = entry else {
+                return Err(Error::Config(format!(
+                    "{key}: {section} is not a section"
+                )));
+            };
+            current = next;
+        }
+        let value = parse_env_value(&raw);
+        if !value.is_str() {
+            typed.push(key.clone());
+        }
+        current.insert(field.clone(), value);
+    }
+    Ok(typed)
+}
+
+/// Reads the value as TOML so numbers, booleans and arrays work, anything
+/// that isn't valid TOML is taken as a plain string. Strings that are valid
+/// TOML otherwise, like a token of only digits, have to be quoted.
+fn parse_env_value(raw: &str) -> Value {
+    format!("value = {raw}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut f| f.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str =
+        "[discord]\ncontrol_guild_id = 1\nrequest_channel_id = 2\n";
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn parse_with(
+        toml: &str,
+        env: &[(&str, &str)],
+    ) -> Result<Config> {
+        parse(toml.parse().unwrap(), vars(env), "test.toml")
+    }
+
+    fn problems(
+        toml: &str,
+        env: &[(&str, &str)],
+    ) -> String {
+        match parse_with(toml, env) {
+            Err(Error::Config(problems)) => problems,
+            other => panic!("expected a config error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn minimal_config_gets_the_defaults() {
+        let config = parse_with(MINIMAL, &[]).unwrap();
+        assert_eq!(config.discord.control_guild_id, 1);
+        assert_eq!(config.database.mode, DatabaseMode::RemoteReplica);
+        assert_eq!(config.runner.poll_interval_secs, 60);
+        assert_eq!(config.http.addr, None);
+    }
+
+    #[test]
+    fn env_values_are_read_as_toml() {
+        assert_eq!(parse_env_value("30"), Value::Integer(30));
+        assert_eq!(parse_env_value("0x003063"), Value::Integer(0x003063));
+        assert_eq!(parse_env_value("true"), Value::Boolean(true));
+        assert_eq!(parse_env_value("0.5"), Value::Float(0.5));
+        assert_eq!(
+            parse_env_value("[1, 2]"),
+            Value::Array(vec![Value::Integer(1), Value::Integer(2)])
+        );
+        assert_eq!(
+            parse_env_value(r#""quoted""#),
+            Value::String("quoted".to_owned())
+        );
+    }
+
+    #[test]
+    fn env_values_that_are_not_toml_stay_strings() {
+        assert_eq!(
+            parse_env_value("0.0.0.0:8081"),
+            Value::String("0.0.0.0:8081".to_owned())
+        );
+        assert_eq!(
+            parse_env_value("https://sentry.example/1"),
+            Value::String("https://sentry.example/1".to_owned())
+        );
+    }
+
+    #[test]
+    fn quoted_env_values_stay_strings() {
+        let config = parse_with(
+            MINIMAL,
+            &[
+                ("FIA__HTTP__WAKE_TOKEN", r#""123456""#),
+                ("FIA__DATABASE__PATH", r#""2024""#),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.http.wake_token.as_deref(), Some("123456"));
+        assert_eq!(config.database.path, "2024");
+    }
+
+    #[test]
+    fn unquoted_numbers_for_strings_point_at_the_variable() {
+        let problems =
+            problems(MINIMAL, &[("FIA__HTTP__WAKE_TOKEN", "123456")]);
+        assert!(problems.contains("invalid type"), "{problems}");
+        assert!(
+            problems.contains("environment: FIA__HTTP__WAKE_TOKEN,"),
+            "{problems}"
+        );
+    }
+
+    #[test]
+    fn env_overrides_nested_keys() {
+        let config = parse_with(
+            "[discord]\ncontrol_guild_id = 1\nrequest_channel_id = 2\n\
+            [runner]\npoll_interval_secs = 10\n",
+            &[
+                ("FIA__RUNNER__POLL_INTERVAL_SECS", "30"),
+                ("FIA__DISCORD__REQUEST_PING_ROLES", "[5, 6]"),
+                ("FIA__HTTP__ADDR", "127.0.0.1:8081"),
+                ("FIA__DATABASE__MODE", "memory"),
+                ("UNRELATED", "ignored"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.runner.poll_interval_secs, 30);
+        // Keys next to the overridden one are kept.
+        assert_eq!(config.runner.max_attempts, 6);
+        assert_eq!(config.discord.request_ping_roles, [5, 6]);
+        assert_eq!(config.http.addr, Some("127.0.0.1:8081".parse().unwrap()));
+        assert_eq!(config.database.mode, DatabaseMode::Memory);
+    }
+
+    #[test]
+    fn category_colors_fall_back_to_the_defaults() {
+        let config = parse_with(
+            MINIMAL,
+            &[("FIA__BRANDING__CATEGORY_COLORS__SUMMONS", "0x123456")],
+        )
+        .unwrap();
+        let colors = &config.branding.category_colors;
+        assert_eq!(colors.summons, 0x123456);
+        assert_eq!(colors.decision, CategoryColors::default().decision);
+        assert_eq!(config.branding.embed_color, 0x003063);
+    }
+
+    #[test]
+    fn env_overrides_fill_in_missing_sections() {
+        let config = parse_with(
+            "",
+            &[
+                ("FIA__DISCORD__CONTROL_GUILD_ID", "1"),
+                ("FIA__DISCORD__REQUEST_CHANNEL_ID", "2"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.discord.request_channel_id, 2);
+    }
+
+    #[test]
+    fn env_override_below_a_value_is_rejected() {
+        let problems =
+            problems(MINIMAL, &[("FIA__DISCORD__CONTROL_GUILD_ID__X", "1")]);
+        assert!(problems.contains("control_guild_id is not a section"));
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_rejected() {
+        let problems =
+            problems(MINIMAL, &[("FIA__RUNNER__POLL_INTERVAL_SECS", "soon")]);
+        assert!(problems.starts_with("test.toml: "), "{problems}");
+        assert!(problems.contains("invalid type"), "{problems}");
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let problems =
+            problems(MINIMAL, &[("FIA__RUNNER__POLL_INTERVAL", "30")]);
+        assert!(problems.contains("poll_interval"), "{problems}");
+    }
+
+    #[test]
+    fn missing_discord_section_is_rejected() {
+        assert!(problems("", &[]).contains("discord"));
+    }
+
+    #[test]
+    fn validation_lists_every_problem() {
+        let problems = problems(
+            "[discord]\ncontrol_guild_id = 0\nrequest_channel_id = 0\n\
+            [branding]\nembed_color = 0x1000000\n\
+            [branding.category_colors]\nsummons = 0x1000000\n\
+            [runner]\npoll_interval_secs = 60\nstall_after_secs = 60\n\
+            retry_base_delay_secs = 60\nretry_max_delay_secs = 30\n",
+            &[],
+        );
+        for expected in [
+            "discord.control_guild_id must be set",
+            "discord.request_channel_id must be set",
+            "branding.embed_color must be at most 0xFFFFFF",
+            "branding.category_colors must all be at most 0xFFFFFF",
+            "runner.stall_after_secs must be above runner.poll_interval_secs",
+            "runner.retry_max_delay_secs must not be below",
+        ] {
+            assert!(problems.contains(expected), "{expected} in {problems}");
+        }
+    }
+
+    #[test]
+    fn validation_checks_urls_rates_and_tokens() {
+        let problems = problems(
+            MINIMAL,
+            &[
+                ("FIA__BRANDING__THUMBNAIL_URL", "ftp://example.com/logo.png"),
+                ("FIA__TELEMETRY__TRACES_SAMPLE_RATE", "1.5"),
+                ("FIA__HTTP__WAKE_TOKEN", r#""""#),
+                ("FIA__DATABASE__MODE", "local"),
+                ("FIA__DATABASE__PATH", r#""""#),
+            ],
+        );
+        for expected in [
+            "branding.thumbnail_url must be an http(s) URL",
+            "telemetry.traces_sample_rate must be between 0 and 1",
+            "http.wake_token must not be empty",
+            "database.path must be set",
+        ] {
+            assert!(problems.contains(expected), "{expected} in {problems}");
+        }
+    }
+}
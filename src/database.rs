@@ -1,12 +1,19 @@
 #![allow(unused)]
 use crate::{
+    config,
     error::Result,
     model::{
-        guild::{self, Guild},
+        allow_request::{AllowRequest, AllowRequestStatus},
+        category::{DocumentCategory, categories_from_key, categories_key},
+        delivery::{Delivery, DeliveryStatus},
+        guild::{self, Guild, SeriesSettings, series_from_key, series_key},
+        health::DestinationHealth,
         thread::Thread,
     },
 };
-use chrono::Utc;
+use std::collections::HashMap;
+
+use chrono::{DateTime, SecondsFormat, Utc};
 use f1_bot_types::{
     Document, DocumentStatus, Event, EventStatus, Image, Series,
 };
@@ -16,12 +23,15 @@ use libsql::{
     params,
 };
 use serde::Serialize;
-use serenity::all::{
-    AutoArchiveDuration, CacheHttp, ChannelId, ChannelType, CreateEmbed,
-    CreateEmbedAuthor, CreateMessage, CreateThread,
-};
+use serenity::all::{CreateEmbed, CreateEmbedAuthor, CreateMessage};
 use tracing::{Instrument, info};
 
+/// Formats a timestamp the same way the database defaults do, so stored
+/// values can be compared as plain strings.
+pub fn timestamp(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
 pub async fn fetch_latest_event_by_series(
     db_conn: &Connection,
     series: Series,
@@ -41,47 +51,6 @@ pub async fn fetch_latest_event_by_series(
     })
 }
 
-pub async fn clear_guild_settings(
-    db_conn: &Connection,
-    guild_id: i64,
-    series: Series,
-) -> Result {
-    let _ = match series {
-        Series::F1 => {
-            db_conn
-                .execute(
-                    r#"UPDATE guilds
-        SET f1_channel = NULL
-        WHERE id = ?"#,
-                    params![guild_id],
-                )
-                .await?
-        },
-        Series::F2 => {
-            db_conn
-                .execute(
-                    r#"UPDATE guilds
-        SET f2_channel = NULL
-        WHERE id = ?"#,
-                    params![guild_id],
-                )
-                .await?
-        },
-        Series::F3 => {
-            db_conn
-                .execute(
-                    r#"UPDATE guilds
-        SET f3_channel = NULL
-        WHERE id = ?"#,
-                    params![guild_id],
-                )
-                .await?
-        },
-        Series::F1Academy => unimplemented!(),
-    };
-    Ok(())
-}
-
 #[tracing::instrument(skip(db_conn))]
 pub async fn fetch_events_by_status(
     db_conn: &Connection,
@@ -131,17 +100,221 @@ pub async fn update_event_status(
     Ok(())
 }
 
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_allow_request(
+    db_conn: &Connection,
+    event_id: u64,
+) -> Result<Option<AllowRequest>> {
+    let mut rows = db_conn
+        .query("SELECT * FROM allow_requests WHERE event_id = ?", [event_id])
+        .await?;
+    Ok(rows.next().await?.map(|f| from_row::<AllowRequest>(&f)).transpose()?)
+}
+
+#[tracing::instrument(skip(db_conn))]
+pub async fn insert_allow_request(
+    db_conn: &Connection,
+    event_id: u64,
+) -> Result<AllowRequest> {
+    db_conn
+        .execute(
+            "INSERT INTO allow_requests (event_id, response) VALUES (?, ?)",
+            params![event_id, AllowRequestStatus::Open.to_str()],
+        )
+        .await?;
+    Ok(AllowRequest {
+        id: db_conn.last_insert_rowid(),
+        event_id,
+        response: AllowRequestStatus::Open,
+        created_at: Utc::now(),
+        approved_by: None,
+        approved_at: None,
+    })
+}
+
+/// Records the answer to an allow request and moves its event along.
+#[tracing::instrument(skip(db_conn))]
+pub async fn resolve_allow_request(
+    db_conn: &Connection,
+    id: i64,
+    user_id: &str,
+    new_status: AllowRequestStatus,
+) -> Result {
+    let tx = db_conn.transaction().await?;
+
+    tx.execute(
+        r#"UPDATE events SET status = ?
+    WHERE id = (
+        SELECT event_id
+        FROM allow_requests
+        WHERE id = ?
+    )
+    "#,
+        params![
+            match new_status {
+                AllowRequestStatus::Allowed => EventStatus::Allowed,
+                AllowRequestStatus::Denied => EventStatus::Denied,
+                _ => EventStatus::NotAllowed,
+            },
+            id
+        ],
+    )
+    .await?;
+
+    tx.execute(
+        r#"UPDATE allow_requests SET response = ?, approved_by = ?, approved_at = ? WHERE id = ?"#,
+        params![new_status, user_id, Utc::now().to_rfc3339(), id],
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Every guild the bot is still a member of.
 #[tracing::instrument(skip(db_conn))]
 pub async fn fetch_guilds(db_conn: &Connection) -> Result<Vec<Guild>> {
-    let mut cursor = db_conn.query("SELECT * FROM guilds", ()).await?;
+    let mut cursor =
+        db_conn.query("SELECT * FROM guilds WHERE left_at IS NULL", ()).await?;
     let mut return_value = vec![];
     while let Ok(Some(row)) = cursor.next().await {
         return_value.push(libsql::de::from_row::<Guild>(&row)?);
     }
+    attach_series_settings(db_conn, &mut return_value).await?;
+
+    Ok(return_value)
+}
+
+/// Guilds that still have the bot and a channel set up for the series.
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_guilds_for_series(
+    db_conn: &Connection,
+    series: Series,
+) -> Result<Vec<Guild>> {
+    let mut cursor = db_conn
+        .query(
+            r#"SELECT guilds.* FROM guilds
+    JOIN guild_series_settings AS s ON s.guild_id = guilds.id
+    WHERE guilds.left_at IS NULL
+    AND s.series = ?
+    AND s.channel_id IS NOT NULL"#,
+            [series_key(series)],
+        )
+        .await?;
+    let mut return_value = vec![];
+    while let Some(row) = cursor.next().await? {
+        return_value.push(libsql::de::from_row::<Guild>(&row)?);
+    }
+    attach_series_settings(db_conn, &mut return_value).await?;
 
     Ok(return_value)
 }
 
+#[derive(serde::Deserialize)]
+struct SeriesSettingsRow {
+    guild_id: i64,
+    series: String,
+    channel_id: Option<String>,
+    role_id: Option<String>,
+    use_threads: bool,
+    categories: Option<String>,
+}
+
+/// Fills in `Guild::series_settings` for all the guilds with one query.
+async fn attach_series_settings(
+    db_conn: &Connection,
+    guilds: &mut [Guild],
+) -> Result {
+    if guilds.is_empty() {
+        return Ok(());
+    }
+    let ids =
+        guilds.iter().map(|f| f.id.to_string()).collect::<Vec<_>>().join(",");
+    let mut cursor = db_conn
+        .query(
+            r#"SELECT * FROM guild_series_settings
+    WHERE guild_id IN (SELECT value FROM json_each(?))"#,
+            [format!("[{ids}]")],
+        )
+        .await?;
+    let mut by_guild: HashMap<i64, Vec<SeriesSettings>> = HashMap::new();
+    while let Some(row) = cursor.next().await? {
+        let row = from_row::<SeriesSettingsRow>(&row)?;
+        let Some(series) = series_from_key(&row.series) else {
+            continue;
+        };
+        by_guild.entry(row.guild_id).or_default().push(SeriesSettings {
+            series,
+            channel: row.channel_id,
+            role: row.role_id,
+            threads: row.use_threads,
+            categories: row.categories.as_deref().map(categories_from_key),
+        });
+    }
+    for guild in guilds {
+        guild.series_settings = by_guild.remove(&guild.id).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Saves the settings of a series for the guild, returns the rows changed,
+/// which is 0 if the guild isn't known.
+#[tracing::instrument(skip(db_conn))]
+pub async fn upsert_series_settings(
+    db_conn: &Connection,
+    discord_id: &str,
+    series: Series,
+    channel: Option<&str>,
+    role: Option<&str>,
+    threads: bool,
+) -> Result<u64> {
+    Ok(db_conn
+        .execute(
+            r#"INSERT INTO guild_series_settings
+        (guild_id, series, channel_id, role_id, use_threads)
+    SELECT id, ?2, ?3, ?4, ?5 FROM guilds WHERE discord_id = ?1
+    ON CONFLICT (guild_id, series) DO UPDATE SET
+        channel_id = excluded.channel_id,
+        role_id = excluded.role_id,
+        use_threads = excluded.use_threads,
+        updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')"#,
+            params![
+                discord_id,
+                series_key(series),
+                channel,
+                role,
+                i64::from(threads)
+            ],
+        )
+        .await?)
+}
+
+/// Sets which categories of documents the series destination gets, `None`
+/// for all of them. Returns the rows changed, 0 if the guild isn't known.
+#[tracing::instrument(skip(db_conn))]
+pub async fn update_series_categories(
+    db_conn: &Connection,
+    discord_id: &str,
+    series: Series,
+    categories: Option<&[DocumentCategory]>,
+) -> Result<u64> {
+    Ok(db_conn
+        .execute(
+            r#"INSERT INTO guild_series_settings
+        (guild_id, series, categories)
+    SELECT id, ?2, ?3 FROM guilds WHERE discord_id = ?1
+    ON CONFLICT (guild_id, series) DO UPDATE SET
+        categories = excluded.categories,
+        updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')"#,
+            params![
+                discord_id,
+                series_key(series),
+                categories.map(categories_key)
+            ],
+        )
+        .await?)
+}
+
 pub async fn fetch_thread_for_discord_guild_and_event(
     db_conn: &Connection,
     guild_id: impl Into<String>,
@@ -150,18 +323,42 @@ pub async fn fetch_thread_for_discord_guild_and_event(
     Ok(None)
 }
 
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_guild_by_id(
+    db_conn: &Connection,
+    guild_id: i64,
+) -> Result<Option<Guild>> {
+    let cursor =
+        db_conn.query("SELECT * FROM guilds WHERE id = ?", [guild_id]).await?;
+
+    fetch_one_guild(db_conn, cursor).await
+}
+
 pub async fn fetch_guild_by_discord_id(
     db_conn: &Connection,
     guild_id: impl ToString,
 ) -> Result<Option<Guild>> {
-    let mut cursor = db_conn
+    let cursor = db_conn
         .query(
             "SELECT * FROM guilds WHERE discord_id = ?",
             params![guild_id.to_string()],
         )
         .await?;
 
-    Ok(cursor.next().await?.map(|f| de::from_row::<Guild>(&f)).transpose()?)
+    fetch_one_guild(db_conn, cursor).await
+}
+
+async fn fetch_one_guild(
+    db_conn: &Connection,
+    mut cursor: libsql::Rows,
+) -> Result<Option<Guild>> {
+    let Some(row) = cursor.next().await? else {
+        return Ok(None);
+    };
+    let mut guild = [de::from_row::<Guild>(&row)?];
+    attach_series_settings(db_conn, &mut guild).await?;
+    let [guild] = guild;
+    Ok(Some(guild))
 }
 
 #[tracing::instrument(skip(db_conn))]
@@ -184,54 +381,6 @@ pub async fn fetch_thread_for_guild_and_event(
     }
 }
 
-#[tracing::instrument(skip(db_conn, http))]
-pub async fn create_new_thread(
-    db_conn: &Connection,
-    http: impl CacheHttp,
-    guild: &Guild,
-    event: &Event,
-) -> Result<Thread> {
-    let (_, Some(channel), true) = guild.settings_for_series(event.series)
-    else {
-        return Err(crate::error::Error::Io(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Invalid Guild Settings",
-        )));
-    };
-
-    let channel_id = ChannelId::new(channel.parse()?);
-    let new_thread = channel_id
-        .create_thread(
-            http,
-            CreateThread::new(format!(
-                "{} {} {}",
-                event.series, event.year, event.title
-            ))
-            .auto_archive_duration(AutoArchiveDuration::ThreeDays)
-            .kind(ChannelType::PublicThread)
-            .audit_log_reason("New Approved FIA Event"),
-        )
-        .await?;
-
-    let thread_id = insert_new_thread(
-        db_conn,
-        &new_thread.id.to_string(),
-        guild.id,
-        event.id as i64,
-        channel,
-    )
-    .await?;
-
-    Ok(Thread {
-        id: thread_id,
-        guild_id: guild.id,
-        event_id: event.id as i64,
-        channel_id: channel.to_string(),
-        discord_id: new_thread.id.to_string(),
-        created_at: Utc::now(),
-    })
-}
-
 #[tracing::instrument(skip(db_conn))]
 pub async fn insert_new_thread(
     db_conn: &Connection,
@@ -287,6 +436,7 @@ pub async fn fetch_images_for_document(
 
 pub fn create_message(
     document: &f1_bot_types::Document,
+    category: DocumentCategory,
     images: Vec<Image>,
 ) -> CreateMessage {
     let mut return_value = vec![];
@@ -294,10 +444,13 @@ pub fn create_message(
         .title(&document.title)
         .url(&document.href)
         .description(format!("[mirror]({})", document.mirror))
-        .color(0x003063)
-        .thumbnail("https://static.ort.dev/fiadontsueme/fia_logo.png")
+        .color(category.color())
+        .thumbnail(&config::get().branding.thumbnail_url)
         .timestamp(document.created_at)
-        .author(CreateEmbedAuthor::new("FIA Document"));
+        .author(CreateEmbedAuthor::new(format!(
+            "FIA Document - {}",
+            category.label()
+        )));
 
     let mut iter = images.into_iter();
     if let Some(image) = iter.next() {
@@ -341,3 +494,536 @@ pub async fn mark_doc_done(
         .await?;
     Ok(())
 }
+
+#[tracing::instrument(skip(db_conn))]
+pub async fn insert_pending_delivery(
+    db_conn: &Connection,
+    document_id: i64,
+    guild_id: i64,
+    channel_id: &str,
+    role_id: Option<&str>,
+) -> Result {
+    db_conn
+        .execute(
+            r#"INSERT INTO deliveries (
+        document_id, guild_id, channel_id, role_id, status
+    ) VALUES (?, ?, ?, ?, ?)
+    ON CONFLICT (document_id, guild_id, channel_id) DO NOTHING"#,
+            params![
+                document_id,
+                guild_id,
+                channel_id,
+                role_id,
+                DeliveryStatus::Pending.to_str()
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Pending deliveries that are due now, across all documents.
+///
+/// Deliveries queued behind one that waits for a retry in the same channel
+/// are held back, so documents arrive in order.
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_due_deliveries(
+    db_conn: &Connection,
+    now: DateTime<Utc>,
+) -> Result<Vec<Delivery>> {
+    let mut cursor = db_conn
+        .query(
+            r#"SELECT * FROM deliveries AS d
+    WHERE d.status = ?1
+    AND (d.next_attempt_at IS NULL OR d.next_attempt_at <= ?2)
+    AND NOT EXISTS (
+        SELECT 1 FROM deliveries AS w
+        WHERE w.channel_id = d.channel_id
+        AND w.status = ?1
+        AND w.next_attempt_at > ?2
+        AND w.id < d.id
+    )
+    ORDER BY d.document_id, d.id"#,
+            params![DeliveryStatus::Pending.to_str(), timestamp(now)],
+        )
+        .await?;
+
+    let mut return_value = vec![];
+    while let Some(row) = cursor.next().await? {
+        return_value.push(from_row(&row)?);
+    }
+
+    Ok(return_value)
+}
+
+/// When the earliest scheduled retry is due, if there is any.
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_next_attempt_at(
+    db_conn: &Connection
+) -> Result<Option<DateTime<Utc>>> {
+    #[derive(serde::Deserialize)]
+    struct NextAttempt {
+        next_attempt_at: Option<DateTime<Utc>>,
+    }
+
+    let mut cursor = db_conn
+        .query(
+            r#"SELECT MIN(next_attempt_at) AS next_attempt_at FROM deliveries
+    WHERE status = ? AND next_attempt_at IS NOT NULL"#,
+            params![DeliveryStatus::Pending.to_str()],
+        )
+        .await?;
+    Ok(match cursor.next().await? {
+        Some(row) => from_row::<NextAttempt>(&row)?.next_attempt_at,
+        None => None,
+    })
+}
+
+/// When a document of the series was last delivered to the guild, if ever.
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_last_sent_at(
+    db_conn: &Connection,
+    guild_id: i64,
+    series: Series,
+) -> Result<Option<DateTime<Utc>>> {
+    #[derive(serde::Deserialize)]
+    struct LastSent {
+        sent_at: Option<DateTime<Utc>>,
+    }
+
+    let mut cursor = db_conn
+        .query(
+            r#"SELECT MAX(deliveries.updated_at) AS sent_at FROM deliveries
+    JOIN documents ON documents.id = deliveries.document_id
+    JOIN events ON events.id = documents.event_id
+    WHERE deliveries.guild_id = ? AND deliveries.status = ?
+        AND events.series = ?"#,
+            params![guild_id, DeliveryStatus::Sent.to_str(), series],
+        )
+        .await?;
+    Ok(match cursor.next().await? {
+        Some(row) => from_row::<LastSent>(&row)?.sent_at,
+        None => None,
+    })
+}
+
+#[tracing::instrument(skip(db_conn))]
+pub async fn mark_delivery_sent(
+    db_conn: &Connection,
+    delivery_id: i64,
+    message_id: &str,
+) -> Result {
+    db_conn
+        .execute(
+            r#"UPDATE deliveries
+    SET status = ?, message_id = ?, error = NULL, updated_at = ?
+    WHERE id = ?"#,
+            params![
+                DeliveryStatus::Sent.to_str(),
+                message_id,
+                timestamp(Utc::now()),
+                delivery_id
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Records a permanent failure, the delivery won't be attempted again.
+#[tracing::instrument(skip(db_conn))]
+pub async fn mark_delivery_failed(
+    db_conn: &Connection,
+    delivery_id: i64,
+    error: &str,
+) -> Result {
+    update_failed_delivery(
+        db_conn,
+        delivery_id,
+        DeliveryStatus::Failed,
+        error,
+        None,
+    )
+    .await
+}
+
+/// Records a transient failure after which no attempts are left.
+#[tracing::instrument(skip(db_conn))]
+pub async fn mark_delivery_dead(
+    db_conn: &Connection,
+    delivery_id: i64,
+    error: &str,
+) -> Result {
+    update_failed_delivery(
+        db_conn,
+        delivery_id,
+        DeliveryStatus::DeadLetter,
+        error,
+        None,
+    )
+    .await
+}
+
+/// Records a transient failure, the delivery stays pending until
+/// `next_attempt_at`.
+#[tracing::instrument(skip(db_conn))]
+pub async fn schedule_delivery_retry(
+    db_conn: &Connection,
+    delivery_id: i64,
+    error: &str,
+    next_attempt_at: DateTime<Utc>,
+) -> Result {
+    update_failed_delivery(
+        db_conn,
+        delivery_id,
+        DeliveryStatus::Pending,
+        error,
+        Some(next_attempt_at),
+    )
+    .await
+}
+
+async fn update_failed_delivery(
+    db_conn: &Connection,
+    delivery_id: i64,
+    status: DeliveryStatus,
+    error: &str,
+    next_attempt_at: Option<DateTime<Utc>>,
+) -> Result {
+    db_conn
+        .execute(
+            r#"UPDATE deliveries
+    SET status = ?, error = ?, attempts = attempts + 1,
+        next_attempt_at = ?, updated_at = ?
+    WHERE id = ?"#,
+            params![
+                status.to_str(),
+                error,
+                next_attempt_at.map(timestamp),
+                timestamp(Utc::now()),
+                delivery_id
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Whether any delivery of the document has not reached a terminal state.
+#[tracing::instrument(skip(db_conn))]
+pub async fn has_open_deliveries(
+    db_conn: &Connection,
+    document_id: i64,
+) -> Result<bool> {
+    let mut cursor = db_conn
+        .query(
+            "SELECT 1 FROM deliveries WHERE document_id = ? AND status = ? LIMIT 1",
+            params![document_id, DeliveryStatus::Pending.to_str()],
+        )
+        .await?;
+    Ok(cursor.next().await?.is_some())
+}
+
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_dead_letters(
+    db_conn: &Connection,
+    limit: u32,
+) -> Result<Vec<Delivery>> {
+    let mut cursor = db_conn
+        .query(
+            "SELECT * FROM deliveries WHERE status = ? ORDER BY updated_at DESC LIMIT ?",
+            params![DeliveryStatus::DeadLetter.to_str(), limit],
+        )
+        .await?;
+
+    let mut return_value = vec![];
+    while let Some(row) = cursor.next().await? {
+        return_value.push(from_row(&row)?);
+    }
+
+    Ok(return_value)
+}
+
+/// Moves dead letters back to pending with a fresh set of attempts.
+/// Replays every dead letter if no id is given, returns the amount replayed.
+#[tracing::instrument(skip(db_conn))]
+pub async fn replay_dead_letters(
+    db_conn: &Connection,
+    delivery_id: Option<i64>,
+) -> Result<u64> {
+    Ok(db_conn
+        .execute(
+            r#"UPDATE deliveries
+    SET status = ?1, attempts = 0, next_attempt_at = NULL, updated_at = ?2
+    WHERE status = ?3 AND (?4 IS NULL OR id = ?4)"#,
+            params![
+                DeliveryStatus::Pending.to_str(),
+                timestamp(Utc::now()),
+                DeliveryStatus::DeadLetter.to_str(),
+                delivery_id
+            ],
+        )
+        .await?)
+}
+
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_document_by_id(
+    db_conn: &Connection,
+    document_id: i64,
+) -> Result<Option<Document>> {
+    let mut cursor = db_conn
+        .query("SELECT * FROM documents WHERE id = ?", [document_id])
+        .await?;
+    Ok(cursor.next().await?.map(|f| from_row::<Document>(&f)).transpose()?)
+}
+
+/// The category stored with the document, `None` if it wasn't classified
+/// yet.
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_document_category(
+    db_conn: &Connection,
+    document_id: i64,
+) -> Result<Option<DocumentCategory>> {
+    let mut cursor = db_conn
+        .query("SELECT category FROM documents WHERE id = ?", [document_id])
+        .await?;
+    Ok(match cursor.next().await? {
+        Some(row) => row
+            .get::<Option<String>>(0)?
+            .as_deref()
+            .and_then(DocumentCategory::from_key),
+        None => None,
+    })
+}
+
+#[tracing::instrument(skip(db_conn))]
+pub async fn set_document_category(
+    db_conn: &Connection,
+    document_id: i64,
+    category: DocumentCategory,
+) -> Result {
+    db_conn
+        .execute(
+            "UPDATE documents SET category = ? WHERE id = ?",
+            params![category.key(), document_id],
+        )
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_destination_health(
+    db_conn: &Connection,
+    guild_id: i64,
+    series: Series,
+) -> Result<Option<DestinationHealth>> {
+    let mut cursor = db_conn
+        .query(
+            "SELECT * FROM destination_health WHERE guild_id = ? AND series = ?",
+            params![guild_id, series_key(series)],
+        )
+        .await?;
+    Ok(cursor
+        .next()
+        .await?
+        .map(|f| from_row::<DestinationHealth>(&f))
+        .transpose()?)
+}
+
+/// Ids of the guilds whose destination for the series is disabled.
+#[tracing::instrument(skip(db_conn))]
+pub async fn fetch_disabled_guild_ids(
+    db_conn: &Connection,
+    series: Series,
+) -> Result<Vec<i64>> {
+    let mut cursor = db_conn
+        .query(
+            r#"SELECT guild_id FROM destination_health
+    WHERE series = ? AND disabled_at IS NOT NULL"#,
+            params![series_key(series)],
+        )
+        .await?;
+
+    let mut return_value = vec![];
+    while let Some(row) = cursor.next().await? {
+        return_value.push(row.get::<i64>(0)?);
+    }
+
+    Ok(return_value)
+}
+
+/// Counts a permanent failure against the destination and returns its
+/// updated health.
+#[tracing::instrument(skip(db_conn))]
+pub async fn record_destination_failure(
+    db_conn: &Connection,
+    guild_id: i64,
+    series: Series,
+    error: &str,
+) -> Result<DestinationHealth> {
+    db_conn
+        .execute(
+            r#"INSERT INTO destination_health (
+        guild_id, series, consecutive_failures, last_error, updated_at
+    ) VALUES (?1, ?2, 1, ?3, ?4)
+    ON CONFLICT (guild_id, series) DO UPDATE SET
+        consecutive_failures = consecutive_failures + 1,
+        last_error = excluded.last_error,
+        updated_at = excluded.updated_at"#,
+            params![guild_id, series_key(series), error, timestamp(Utc::now())],
+        )
+        .await?;
+
+    fetch_destination_health(db_conn, guild_id, series)
+        .await?
+        .ok_or(crate::error::Error::NotFound("destination health"))
+}
+
+/// Disables the destination, returns `false` if it already was disabled.
+#[tracing::instrument(skip(db_conn))]
+pub async fn disable_destination(
+    db_conn: &Connection,
+    guild_id: i64,
+    series: Series,
+) -> Result<bool> {
+    let changed = db_conn
+        .execute(
+            r#"UPDATE destination_health SET disabled_at = ?
+    WHERE guild_id = ? AND series = ? AND disabled_at IS NULL"#,
+            params![timestamp(Utc::now()), guild_id, series_key(series)],
+        )
+        .await?;
+    Ok(changed > 0)
+}
+
+/// Forgets past failures and re-enables the destination.
+#[tracing::instrument(skip(db_conn))]
+pub async fn reset_destination_health(
+    db_conn: &Connection,
+    guild_id: i64,
+    series: Series,
+) -> Result {
+    db_conn
+        .execute(
+            r#"UPDATE destination_health
+    SET consecutive_failures = 0, last_error = NULL, disabled_at = NULL,
+        updated_at = ?
+    WHERE guild_id = ? AND series = ?
+    AND (consecutive_failures > 0 OR disabled_at IS NOT NULL)"#,
+            params![timestamp(Utc::now()), guild_id, series_key(series)],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Adds a guild the bot joined, a guild that left and came back keeps its
+/// settings.
+#[tracing::instrument(skip(db_conn))]
+pub async fn upsert_guild(
+    db_conn: &Connection,
+    discord_id: &str,
+    name: &str,
+    joined_at: DateTime<Utc>,
+) -> Result {
+    db_conn
+        .execute(
+            r#"INSERT INTO guilds (discord_id, name, joined_at) 
+        VALUES (?, ?, ?) 
+        ON CONFLICT(discord_id) 
+        DO UPDATE SET 
+        name = excluded.name,
+        left_at = NULL"#,
+            params![discord_id, name, joined_at.to_rfc3339()],
+        )
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_conn))]
+pub async fn rename_guild(
+    db_conn: &Connection,
+    discord_id: &str,
+    name: &str,
+) -> Result {
+    db_conn
+        .execute(
+            r#"UPDATE guilds SET name = ? WHERE discord_id = ?"#,
+            params![name, discord_id],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Remembers that the bot was removed from the guild, the settings are kept
+/// around until the retention window ran out. Pending deliveries to the guild
+/// won't ever succeed, so they fail right away.
+#[tracing::instrument(skip(db_conn))]
+pub async fn mark_guild_left(
+    db_conn: &Connection,
+    discord_id: &str,
+) -> Result {
+    let now = timestamp(Utc::now());
+    let tx = db_conn.transaction().await?;
+    tx.execute(
+        "UPDATE guilds SET left_at = ? WHERE discord_id = ? AND left_at IS NULL",
+        params![now.clone(), discord_id],
+    )
+    .await?;
+    tx.execute(
+        r#"UPDATE deliveries SET status = ?, error = ?, updated_at = ?
+    WHERE status = ?
+    AND guild_id = (SELECT id FROM guilds WHERE discord_id = ?)"#,
+        params![
+            DeliveryStatus::Failed.to_str(),
+            "The bot was removed from the guild",
+            now,
+            DeliveryStatus::Pending.to_str(),
+            discord_id
+        ],
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Clears the `left_at` marker of a guild that added the bot again.
+#[tracing::instrument(skip(db_conn))]
+pub async fn restore_guild(
+    db_conn: &Connection,
+    discord_id: &str,
+) -> Result<bool> {
+    let changed = db_conn
+        .execute(
+            "UPDATE guilds SET left_at = NULL WHERE discord_id = ? AND left_at IS NOT NULL",
+            params![discord_id],
+        )
+        .await?;
+    Ok(changed > 0)
+}
+
+/// Deletes every trace of guilds that left before `cutoff`, returns the
+/// amount of guilds purged.
+#[tracing::instrument(skip(db_conn))]
+pub async fn purge_departed_guilds(
+    db_conn: &Connection,
+    cutoff: DateTime<Utc>,
+) -> Result<u64> {
+    let cutoff = timestamp(cutoff);
+    let departed =
+        "SELECT id FROM guilds WHERE left_at IS NOT NULL AND left_at < ?";
+    let tx = db_conn.transaction().await?;
+    for table in
+        ["deliveries", "destination_health", "guild_series_settings", "threads"]
+    {
+        tx.execute(
+            &format!("DELETE FROM {table} WHERE guild_id IN ({departed})"),
+            params![cutoff.clone()],
+        )
+        .await?;
+    }
+    let purged = tx
+        .execute(
+            "DELETE FROM guilds WHERE left_at IS NOT NULL AND left_at < ?",
+            params![cutoff],
+        )
+        .await?;
+    tx.commit().await?;
+    Ok(purged)
+}
@@ -1,5 +1,18 @@
 use std::{error::Error as StdErr, num::ParseIntError};
 
+use serenity::all::{HttpError, ModelError, Permissions};
+
+/// JSON error codes Discord answers with, the ones that get their own
+/// variant.
+const UNKNOWN_CHANNEL: isize = 10003;
+const MISSING_ACCESS: isize = 50001;
+const MISSING_PERMISSIONS: isize = 50013;
+
+/// SQLite result codes of a database that is busy with another connection,
+/// worth waiting out.
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+
 #[derive(Debug)]
 pub enum Error {
     Serenity(serenity::Error),
@@ -7,7 +20,53 @@ pub enum Error {
     Serde(serde::de::value::Error),
     Io(std::io::Error),
     ParseInt(ParseIntError),
+    /// Missing or invalid configuration.
+    Config(String),
+    /// The database was migrated by a newer build than this one.
+    SchemaTooNew {
+        found: i64,
+        supported: i64,
+    },
+    /// The settings a guild saved for a series can't be used as they are.
+    InvalidSettings(String),
+    /// The bot lacks permissions it needs, empty if Discord didn't say which.
+    MissingPermission(Permissions),
+    /// The channel was deleted or the bot can't see it anymore.
+    UnknownChannel,
+    /// Discord wants the bot to slow down.
+    RateLimited,
+    /// Something the bot stored itself is missing, named by the string.
+    NotFound(&'static str),
+    /// Nothing matches what a user asked for, named by the string.
+    NoSuch(&'static str),
 }
+
+/// How an error should be handled, decides about retries and whether
+/// anybody gets alerted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Likely to go away on its own, worth another try later.
+    Transient,
+    /// Caused by how a guild set the bot up or by what was asked for.
+    /// Retrying won't help, the guild has to fix it, so it's shown to them
+    /// instead of being reported.
+    User,
+    /// A bug or a broken deployment. Retrying won't help and it gets
+    /// reported.
+    Internal,
+}
+
+impl ErrorClass {
+    /// Name used for metric labels and log fields.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorClass::Transient => "transient",
+            ErrorClass::User => "user",
+            ErrorClass::Internal => "internal",
+        }
+    }
+}
+
 use core::result::Result as StdResult;
 
 pub type Result<T = ()> = StdResult<T, Error>;
@@ -20,6 +79,16 @@ impl StdErr for Error {
             Error::Serde(error) => error.source(),
             Error::Io(error) => error.source(),
             Error::ParseInt(error) => error.source(),
+            Error::Config(_)
+            | Error::SchemaTooNew {
+                ..
+            }
+            | Error::InvalidSettings(_)
+            | Error::MissingPermission(_)
+            | Error::UnknownChannel
+            | Error::RateLimited
+            | Error::NotFound(_)
+            | Error::NoSuch(_) => None,
         }
     }
 }
@@ -35,10 +104,138 @@ impl std::fmt::Display for Error {
             Error::Serde(error) => write!(f, "{error}"),
             Error::Io(error) => write!(f, "{error}"),
             Error::ParseInt(error) => write!(f, "{error}"),
+            Error::Config(problem) => {
+                write!(f, "invalid configuration: {problem}")
+            },
+            Error::SchemaTooNew {
+                found,
+                supported,
+            } => write!(
+                f,
+                "database schema version {found} is newer than the \
+                supported version {supported}, refusing to start"
+            ),
+            Error::InvalidSettings(problem) => {
+                write!(f, "invalid guild settings: {problem}")
+            },
+            Error::MissingPermission(permissions) if permissions.is_empty() => {
+                write!(f, "missing access")
+            },
+            Error::MissingPermission(permissions) => write!(
+                f,
+                "missing permissions: {}",
+                permissions.get_permission_names().join(", ")
+            ),
+            Error::UnknownChannel => write!(f, "unknown channel"),
+            Error::RateLimited => write!(f, "rate limited by Discord"),
+            Error::NotFound(what) => write!(f, "{what} not found"),
+            Error::NoSuch(what) => write!(f, "no such {what}"),
         }
     }
 }
 
+impl Error {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Error::Serenity(error) => serenity_class(error),
+            Error::Libsql(error) => libsql_class(error),
+            Error::Io(_) | Error::RateLimited => ErrorClass::Transient,
+            Error::InvalidSettings(_)
+            | Error::MissingPermission(_)
+            | Error::UnknownChannel
+            | Error::NoSuch(_) => ErrorClass::User,
+            Error::Serde(_)
+            | Error::ParseInt(_)
+            | Error::Config(_)
+            | Error::NotFound(_)
+            | Error::SchemaTooNew {
+                ..
+            } => ErrorClass::Internal,
+        }
+    }
+
+    /// Whether the error should end up in Sentry.
+    pub fn is_reportable(&self) -> bool {
+        self.class() != ErrorClass::User
+    }
+
+    /// Explanation for guild admins, without internals they can't act on.
+    pub fn user_message(&self) -> String {
+        match self {
+            Error::InvalidSettings(problem) => {
+                format!("The settings can't be used: {problem}")
+            },
+            Error::MissingPermission(permissions) if permissions.is_empty() => {
+                "The bot doesn't have access to the channel. Check the \
+                channel permissions of the bot's role."
+                    .to_owned()
+            },
+            Error::MissingPermission(permissions) => format!(
+                "The bot is missing these permissions: {}. Check the channel \
+                permissions of the bot's role.",
+                permissions.get_permission_names().join(", ")
+            ),
+            Error::UnknownChannel => "The channel was deleted or the bot \
+                can't see it anymore. Pick another one with `/settings`."
+                .to_owned(),
+            Error::RateLimited => {
+                "Discord is rate limiting the bot, try again in a minute."
+                    .to_owned()
+            },
+            Error::NotFound(what) => {
+                format!("No {what} found, this was reported.")
+            },
+            Error::NoSuch(what) => format!("No {what} found."),
+            _ => match self.class() {
+                ErrorClass::Transient => "Something went wrong talking to \
+                    Discord or the database, try again in a minute."
+                    .to_owned(),
+                _ => "Something went wrong on our side, it was reported."
+                    .to_owned(),
+            },
+        }
+    }
+}
+
+/// Class of the serenity errors [`From<serenity::Error>`] didn't turn into
+/// a variant of their own. The ones caused by a guild's setup got one
+/// already, so a permanent failure left over is a bad request.
+fn serenity_class(error: &serenity::Error) -> ErrorClass {
+    match error {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+            let status = response.status_code;
+            if status.is_server_error() || status.as_u16() == 429 {
+                ErrorClass::Transient
+            } else {
+                // Unknown Channel (10003), Missing Access (50001),
+                // Missing Permissions (50013) and every other 4xx.
+                ErrorClass::Internal
+            }
+        },
+        serenity::Error::Http(HttpError::Request(_)) => ErrorClass::Transient,
+        serenity::Error::Io(_) => ErrorClass::Transient,
+        serenity::Error::Model(_) => ErrorClass::Internal,
+        _ => ErrorClass::Transient,
+    }
+}
+
+/// Only failing to reach the database, or waiting on a lock for too long,
+/// is an outage. Everything else is a query that won't work on a retry
+/// either.
+fn libsql_class(error: &libsql::Error) -> ErrorClass {
+    match error {
+        libsql::Error::ConnectionFailed(_)
+        | libsql::Error::Replication(_)
+        | libsql::Error::Sync(_) => ErrorClass::Transient,
+        libsql::Error::SqliteFailure(code, _)
+            if matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED) =>
+        {
+            ErrorClass::Transient
+        },
+        _ => ErrorClass::Internal,
+    }
+}
+
 impl From<ParseIntError> for Error {
     fn from(value: ParseIntError) -> Self {
         Self::ParseInt(value)
@@ -51,9 +248,29 @@ impl From<std::io::Error> for Error {
     }
 }
 
+/// Errors the bot runs into in normal operation get their own variant,
+/// everything else stays wrapped.
 impl From<serenity::Error> for Error {
     fn from(value: serenity::Error) -> Self {
-        Self::Serenity(value)
+        match &value {
+            serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+                if response.status_code.as_u16() == 429 {
+                    return Self::RateLimited;
+                }
+                match response.error.code {
+                    UNKNOWN_CHANNEL => Self::UnknownChannel,
+                    MISSING_ACCESS | MISSING_PERMISSIONS => {
+                        Self::MissingPermission(Permissions::empty())
+                    },
+                    _ => Self::Serenity(value),
+                }
+            },
+            serenity::Error::Model(ModelError::InvalidPermissions {
+                required,
+                present,
+            }) => Self::MissingPermission(required.difference(*present)),
+            _ => Self::Serenity(value),
+        }
     }
 }
 
@@ -68,3 +285,59 @@ impl From<serde::de::value::Error> for Error {
         Self::Serde(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_rows_are_internal() {
+        let error = Error::NotFound("destination health");
+        assert_eq!(error.class(), ErrorClass::Internal);
+        assert!(error.is_reportable());
+    }
+
+    #[test]
+    fn lookups_of_user_input_are_user_errors() {
+        let error = Error::NoSuch("event");
+        assert_eq!(error.class(), ErrorClass::User);
+        assert!(!error.is_reportable());
+        assert_eq!(error.user_message(), "No event found.");
+    }
+
+    #[test]
+    fn outages_are_transient() {
+        let io = serenity::Error::Io(std::io::Error::other("outage"));
+        assert_eq!(Error::Serenity(io).class(), ErrorClass::Transient);
+        assert_eq!(Error::RateLimited.class(), ErrorClass::Transient);
+        let offline = libsql::Error::ConnectionFailed("refused".to_owned());
+        assert_eq!(Error::Libsql(offline).class(), ErrorClass::Transient);
+        let busy = libsql::Error::SqliteFailure(5, "locked".to_owned());
+        assert_eq!(Error::Libsql(busy).class(), ErrorClass::Transient);
+    }
+
+    #[test]
+    fn broken_queries_are_internal() {
+        let syntax = libsql::Error::SqliteFailure(1, "syntax".to_owned());
+        assert_eq!(Error::Libsql(syntax).class(), ErrorClass::Internal);
+        let column = libsql::Error::InvalidColumnName("nope".to_owned());
+        assert_eq!(Error::Libsql(column).class(), ErrorClass::Internal);
+        assert!(Error::Libsql(libsql::Error::NullValue).is_reportable());
+    }
+
+    #[test]
+    fn invalid_permissions_name_what_is_missing() {
+        let error = Error::from(serenity::Error::Model(
+            ModelError::InvalidPermissions {
+                required: Permissions::SEND_MESSAGES | Permissions::EMBED_LINKS,
+                present: Permissions::SEND_MESSAGES,
+            },
+        ));
+        assert!(matches!(
+            error,
+            Error::MissingPermission(missing)
+                if missing == Permissions::EMBED_LINKS
+        ));
+        assert_eq!(error.class(), ErrorClass::User);
+    }
+}
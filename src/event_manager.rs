@@ -1,38 +1,49 @@
 use std::process::exit;
+use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 
-use chrono::Utc;
-use f1_bot_types::EventStatus;
-use libsql::{Connection, params};
 use sentry::Hub;
 use sentry::TransactionContext;
 use serenity::all::{
-    ComponentInteraction, CreateActionRow, CreateButton,
-    CreateInteractionResponseFollowup, EditMessage, Message, UserId,
+    ComponentInteraction, CreateActionRow, CreateButton, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseFollowup,
+    CreateInteractionResponseMessage, EditInteractionResponse, EditMessage,
+    Message, UserId,
 };
 use serenity::{
     all::{
-        ActivityType, Guild, GuildId, Interaction, PartialGuild, ResumedEvent,
-        UnavailableGuild,
+        ActivityType, Guild, GuildId, Interaction, PartialGuild, RatelimitInfo,
+        ResumedEvent, UnavailableGuild,
     },
     async_trait,
     prelude::*,
 };
 
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::commands;
-use crate::runner::{AllowRequestStatus, runner};
+use crate::config;
+use crate::gateway::{Gateway, SerenityGateway};
+use crate::metrics;
+use crate::model::allow_request::AllowRequestStatus;
+use crate::runner::{
+    guild_cache::GuildCache, heartbeat::Heartbeat, runner,
+    scheduler::Scheduler, shutdown::Shutdown, wakeup::Wakeup,
+};
+use crate::storage::Storage;
 
 pub async fn allow_request(
-    db_conn: &Connection,
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    wakeup: &Wakeup,
     id: i64,
     cmd: ComponentInteraction,
     ctx: &impl CacheHttp,
 ) -> Result<(), crate::error::Error> {
     cmd.defer(ctx).await?;
-    disable_buttons(&cmd.message, ctx, id).await?;
+    disable_buttons(gateway, &cmd.message, id).await?;
     cmd.create_followup(
         ctx,
         CreateInteractionResponseFollowup::new().content(format!(
@@ -42,57 +53,43 @@ pub async fn allow_request(
         )),
     )
     .await?;
-    update_allow_request(db_conn, id, cmd.user.id, AllowRequestStatus::Allowed)
-        .await?;
+    update_allow_request(
+        storage,
+        wakeup,
+        id,
+        cmd.user.id,
+        AllowRequestStatus::Allowed,
+    )
+    .await?;
+    metrics::observe_approval(cmd.message.timestamp.to_utc(), "allowed");
     Ok(())
 }
 
 pub async fn update_allow_request(
-    db_conn: &Connection,
+    storage: &dyn Storage,
+    wakeup: &Wakeup,
     id: i64,
     user_id: UserId,
     new_status: AllowRequestStatus,
 ) -> Result<(), crate::error::Error> {
-    let tx = db_conn.transaction().await?;
-
-    tx.execute(
-        r#"UPDATE events SET status = ?
-    WHERE id = (
-        SELECT event_id
-        FROM allow_requests
-        WHERE id = ?
-    )
-    "#,
-        params![
-            match new_status {
-                AllowRequestStatus::Allowed => EventStatus::Allowed,
-                AllowRequestStatus::Denied => EventStatus::Denied,
-                _ => EventStatus::NotAllowed,
-            },
-            id
-        ],
-    )
-    .await?;
-
-    tx.execute(
-        r#"UPDATE allow_requests SET response = ?, approved_by = ?, approved_at = ? WHERE id = ?"#,
-        params![new_status, user_id.to_string(), Utc::now().to_rfc3339(), id],
-    )
-    .await?;
-
-    tx.commit().await?;
+    let allowed = matches!(new_status, AllowRequestStatus::Allowed);
+    storage.resolve_allow_request(id, &user_id.to_string(), new_status).await?;
+    // Newly allowed events should be posted right away.
+    if allowed {
+        wakeup.notify();
+    }
     Ok(())
 }
 
 pub async fn disable_buttons(
+    gateway: &dyn Gateway,
     message: &Message,
-    ctx: &impl CacheHttp,
     id: i64,
 ) -> Result<(), crate::error::Error> {
-    message
-        .clone()
-        .edit(
-            ctx,
+    gateway
+        .edit_message(
+            message.channel_id,
+            message.id,
             EditMessage::new().components(vec![CreateActionRow::Buttons(
                 vec![
                     CreateButton::new(format!("allow-{id}"))
@@ -112,13 +109,15 @@ pub async fn disable_buttons(
 }
 
 pub async fn deny_request(
-    db_conn: &Connection,
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    wakeup: &Wakeup,
     id: i64,
     cmd: ComponentInteraction,
     ctx: &impl CacheHttp,
 ) -> Result<(), crate::error::Error> {
     cmd.defer(ctx).await?;
-    disable_buttons(&cmd.message, ctx, id).await?;
+    disable_buttons(gateway, &cmd.message, id).await?;
     cmd.create_followup(
         ctx,
         CreateInteractionResponseFollowup::new().content(format!(
@@ -128,15 +127,28 @@ pub async fn deny_request(
         )),
     )
     .await?;
-    update_allow_request(db_conn, id, cmd.user.id, AllowRequestStatus::Denied)
-        .await?;
+    update_allow_request(
+        storage,
+        wakeup,
+        id,
+        cmd.user.id,
+        AllowRequestStatus::Denied,
+    )
+    .await?;
+    metrics::observe_approval(cmd.message.timestamp.to_utc(), "denied");
 
     Ok(())
 }
 
 pub struct BotEvents {
     pub thread_lock: AtomicBool,
-    pub conn: &'static libsql::Connection,
+    pub storage: Arc<dyn Storage>,
+    pub wakeup: Arc<Wakeup>,
+    pub scheduler: Arc<Scheduler>,
+    pub guild_cache: Arc<GuildCache>,
+    pub shutdown: Arc<Shutdown>,
+    pub heartbeat: Arc<Heartbeat>,
+    pub poll_interval: Duration,
 }
 
 #[async_trait]
@@ -177,10 +189,12 @@ impl EventHandler for BotEvents {
         span.finish();
 
         let span = tx.start_child("discord", "Create Control-guild Commands");
+        let control_guild =
+            GuildId::new(config::get().discord.control_guild_id);
         if let Err(why) = ctx
             .http
             .create_guild_command(
-                GuildId::new(883847530687913995),
+                control_guild,
                 &crate::commands::sync::register(),
             )
             .await
@@ -191,7 +205,7 @@ impl EventHandler for BotEvents {
         if let Err(why) = ctx
             .http
             .create_guild_command(
-                GuildId::new(883847530687913995),
+                control_guild,
                 &crate::commands::shutdown::register(),
             )
             .await
@@ -199,13 +213,35 @@ impl EventHandler for BotEvents {
             sentry::capture_error(&why);
             error!("Error creating sync command: {why:#?}");
         }
+        if let Err(why) = ctx
+            .http
+            .create_guild_command(
+                control_guild,
+                &crate::commands::deliveries::register(),
+            )
+            .await
+        {
+            sentry::capture_error(&why);
+            error!("Error creating deliveries command: {why:#?}");
+        }
         span.finish();
         tx.set_status(sentry::protocol::SpanStatus::Ok);
         tx.finish();
 
         if !self.thread_lock.load(Ordering::Relaxed) {
             self.thread_lock.store(true, Ordering::Relaxed);
-            if let Err(why) = runner(self.conn, &ctx.clone()).await {
+            if let Err(why) = runner(
+                self.storage.as_ref(),
+                &SerenityGateway::new(&ctx),
+                &self.wakeup,
+                &self.scheduler,
+                &self.guild_cache,
+                &self.shutdown,
+                &self.heartbeat,
+                self.poll_interval,
+            )
+            .await
+            {
                 sentry::capture_error(&why);
                 error!("{why:#?}");
             }
@@ -244,6 +280,7 @@ impl EventHandler for BotEvents {
         ));
 
         let hub = Hub::new_from_top(Hub::current());
+        let gateway = SerenityGateway::new(&ctx);
         tx.set_data("interaction", serde_json::to_value(&interaction).unwrap());
         match interaction {
             Interaction::Component(cmd) => {
@@ -258,18 +295,63 @@ impl EventHandler for BotEvents {
                         ..Default::default()
                     }))
                 });
+                let reply_to = cmd.clone();
                 let Some((kind, id)) = cmd.data.custom_id.split_once("-")
                 else {
                     return;
                 };
                 match match kind {
                     "allow" => {
-                        allow_request(self.conn, id.parse().unwrap(), cmd, &ctx)
-                            .await
+                        allow_request(
+                            self.storage.as_ref(),
+                            &gateway,
+                            &self.wakeup,
+                            id.parse().unwrap(),
+                            cmd,
+                            &ctx,
+                        )
+                        .await
                     },
                     "deny" => {
-                        deny_request(self.conn, id.parse().unwrap(), cmd, &ctx)
-                            .await
+                        deny_request(
+                            self.storage.as_ref(),
+                            &gateway,
+                            &self.wakeup,
+                            id.parse().unwrap(),
+                            cmd,
+                            &ctx,
+                        )
+                        .await
+                    },
+                    "save" => {
+                        commands::set::save_anyway(
+                            self.storage.as_ref(),
+                            &self.guild_cache,
+                            &ctx,
+                            cmd,
+                            id,
+                        )
+                        .await
+                    },
+                    "clear" => {
+                        commands::set::clear_series(
+                            self.storage.as_ref(),
+                            &self.guild_cache,
+                            &ctx,
+                            cmd,
+                            id,
+                        )
+                        .await
+                    },
+                    "categories" => {
+                        commands::set::update_categories(
+                            self.storage.as_ref(),
+                            &self.guild_cache,
+                            &ctx,
+                            cmd,
+                            id,
+                        )
+                        .await
                     },
                     _ => Ok(()),
                 } {
@@ -279,27 +361,70 @@ impl EventHandler for BotEvents {
                     },
                     Err(why) => {
                         tx.set_status(sentry::protocol::SpanStatus::Cancelled);
-                        hub.capture_error(&why);
+                        if why.is_reportable() {
+                            hub.capture_error(&why);
+                        }
                         error!("Interaction Error: {why:#?}");
+                        report_component_error(&ctx, &reply_to, &why).await;
                         tx.finish();
                     },
                 }
             },
             Interaction::Command(cmd) => {
+                let reply_to = cmd.clone();
                 if let Err(why) = match cmd.data.name.as_str() {
                     "settings" => {
-                        commands::set::run(self.conn, &ctx, cmd).await
+                        commands::set::run(
+                            self.storage.as_ref(),
+                            &gateway,
+                            &self.scheduler,
+                            &self.guild_cache,
+                            &ctx,
+                            cmd,
+                        )
+                        .await
                     },
                     "sync" => commands::sync::run(&ctx, cmd).await,
                     "shutdown" => commands::shutdown::run(&ctx, cmd).await,
+                    "deliveries" => {
+                        commands::deliveries::run(
+                            self.storage.as_ref(),
+                            &ctx,
+                            cmd,
+                        )
+                        .await
+                    },
                     "check-repost" => {
-                        commands::repost::run(self.conn, &ctx, cmd).await
+                        commands::repost::run(
+                            self.storage.as_ref(),
+                            &gateway,
+                            &ctx,
+                            cmd,
+                        )
+                        .await
                     },
                     _ => Ok(()),
                 } {
                     tx.set_status(sentry::protocol::SpanStatus::Cancelled);
-                    hub.capture_error(&why);
+                    if why.is_reportable() {
+                        hub.capture_error(&why);
+                    }
                     error!("cmd error: {why}");
+                    // Every command responded or deferred before anything
+                    // that can fail, so there is a response to edit.
+                    let embed = CreateEmbed::new()
+                        .title("Error")
+                        .description(why.user_message())
+                        .color(0xFF0000);
+                    if let Err(why) = reply_to
+                        .edit_response(
+                            &ctx,
+                            EditInteractionResponse::new().embed(embed),
+                        )
+                        .await
+                    {
+                        warn!("Error reporting cmd error: {why}");
+                    }
                     tx.finish();
                 } else {
                     tx.set_status(sentry::protocol::SpanStatus::Ok);
@@ -317,7 +442,19 @@ impl EventHandler for BotEvents {
         _is_new: Option<bool>,
     ) {
         match _is_new {
-            None | Some(false) => return,
+            None | Some(false) => {
+                // The guild might have added the bot while it was offline.
+                match restore_or_add_guild(self.storage.as_ref(), &guild).await
+                {
+                    Ok(true) => self.guild_cache.invalidate().await,
+                    Ok(false) => {},
+                    Err(why) => {
+                        sentry::capture_error(&why);
+                        error!("Error restoring guild: {why}");
+                    },
+                }
+                return;
+            },
             Some(true) => {},
         }
         let tx = sentry::start_transaction(TransactionContext::new(
@@ -326,24 +463,18 @@ impl EventHandler for BotEvents {
         ));
         let span = tx.start_child("db", "Insert new Guild");
         if let Err(why) = self
-            .conn
-            .execute(
-                r#"INSERT INTO guilds (discord_id, name, joined_at) 
-        VALUES (?, ?, ?) 
-        ON CONFLICT(discord_id) 
-        DO UPDATE SET 
-        name = excluded.name"#,
-                params![
-                    guild.id.to_string(),
-                    guild.name.clone(),
-                    guild.joined_at.to_utc().to_rfc3339(),
-                ],
+            .storage
+            .upsert_guild(
+                &guild.id.to_string(),
+                &guild.name,
+                guild.joined_at.to_utc(),
             )
             .await
         {
             sentry::capture_error(&why);
             error!("{why}");
         }
+        self.guild_cache.invalidate().await;
         span.finish();
         tx.set_status(sentry::protocol::SpanStatus::Ok);
         tx.finish();
@@ -369,16 +500,14 @@ impl EventHandler for BotEvents {
 
         let span = tx.start_child("db", "Update guild");
         if let Err(why) = self
-            .conn
-            .execute(
-                r#"UPDATE guilds SET name = ? WHERE discord_id = ?"#,
-                params![new_incomplete.name, new_incomplete.id.to_string()],
-            )
+            .storage
+            .rename_guild(&new_incomplete.id.to_string(), &new_incomplete.name)
             .await
         {
             sentry::capture_error(&why);
             error!("Error updating guild: {why}");
         } else {
+            self.guild_cache.invalidate().await;
             tx.set_status(sentry::protocol::SpanStatus::Ok)
         }
         span.finish();
@@ -394,6 +523,37 @@ impl EventHandler for BotEvents {
         if incomplete.unavailable {
             return;
         }
+        let tx = sentry::start_transaction(TransactionContext::new(
+            "guild-delete",
+            "discord",
+        ));
+        let span = tx.start_child("db", "Mark guild left");
+        if let Err(why) =
+            self.storage.mark_guild_left(&incomplete.id.to_string()).await
+        {
+            sentry::capture_error(&why);
+            error!("Error marking guild as left: {why}");
+        } else {
+            self.guild_cache.invalidate().await;
+            tx.set_status(sentry::protocol::SpanStatus::Ok);
+        }
+        span.finish();
+        tx.finish();
+    }
+
+    async fn ratelimit(
+        &self,
+        data: RatelimitInfo,
+    ) {
+        warn!(
+            path = data.path,
+            global = data.global,
+            timeout_ms = data.timeout.as_millis() as u64,
+            "Hit a rate limit"
+        );
+        if data.global {
+            self.scheduler.pause(data.timeout);
+        }
     }
 
     async fn resume(
@@ -403,3 +563,55 @@ impl EventHandler for BotEvents {
     ) {
     }
 }
+
+/// Brings back a guild that removed the bot and stores one that added it
+/// while the bot was offline. `true` if the stored guilds changed.
+async fn restore_or_add_guild(
+    storage: &dyn Storage,
+    guild: &Guild,
+) -> Result<bool, crate::error::Error> {
+    let discord_id = guild.id.to_string();
+    if storage.restore_guild(&discord_id).await? {
+        return Ok(true);
+    }
+    if storage.fetch_guild_by_discord_id(&discord_id).await?.is_some() {
+        return Ok(false);
+    }
+    storage
+        .upsert_guild(&discord_id, &guild.name, guild.joined_at.to_utc())
+        .await?;
+    Ok(true)
+}
+
+/// Shows the user why clicking a button or picking from a menu didn't work.
+/// Components answer with the outcome only once it's known, so usually there
+/// is nothing to edit yet, otherwise the error goes out as a follow-up.
+async fn report_component_error(
+    ctx: &Context,
+    cmd: &ComponentInteraction,
+    why: &crate::error::Error,
+) {
+    let embed = CreateEmbed::new()
+        .title("Error")
+        .description(why.user_message())
+        .color(0xFF0000);
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .ephemeral(true)
+            .embed(embed.clone()),
+    );
+    if cmd.create_response(ctx, response).await.is_ok() {
+        return;
+    }
+    if let Err(why) = cmd
+        .create_followup(
+            ctx,
+            CreateInteractionResponseFollowup::new()
+                .ephemeral(true)
+                .embed(embed),
+        )
+        .await
+    {
+        warn!("Error reporting interaction error: {why}");
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use ::serenity::{
+    all::{ChannelId, CreateMessage, EditMessage, GuildId, MessageId},
+    async_trait,
+};
+use f1_bot_types::Event;
+
+use crate::error::Result;
+
+#[cfg(test)]
+pub mod recording;
+mod serenity;
+
+pub use self::serenity::SerenityGateway;
+
+/// Everything the bot sends to Discord on its own, as opposed to answering
+/// an interaction.
+#[async_trait]
+pub trait Gateway: Send + Sync {
+    /// Opens a public thread for an event in the channel, returns the id of
+    /// the thread.
+    async fn create_thread(
+        &self,
+        channel_id: ChannelId,
+        name: String,
+    ) -> Result<ChannelId>;
+
+    async fn send_message(
+        &self,
+        channel_id: ChannelId,
+        message: CreateMessage,
+    ) -> Result<MessageId>;
+
+    async fn edit_message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        message: EditMessage,
+    ) -> Result;
+
+    /// Asks the maintainers in the request channel to allow or deny the
+    /// event.
+    async fn send_allow_request(
+        &self,
+        event: &Event,
+        request_id: i64,
+    ) -> Result<MessageId>;
+
+    /// Messages the owner of the guild, or its system channel if the owner
+    /// can't be messaged, or `discord.notice_fallback_channel_id` if neither
+    /// works.
+    async fn notify_guild(
+        &self,
+        guild_id: GuildId,
+        message: CreateMessage,
+    ) -> Result;
+
+    /// Time until a message to the channel can go out without running into
+    /// a rate limit, `None` if it can right away.
+    async fn channel_backoff(
+        &self,
+        channel_id: ChannelId,
+    ) -> Option<Duration>;
+}
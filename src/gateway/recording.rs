@@ -0,0 +1,262 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use ::serenity::{
+    all::{
+        ChannelId, CreateMessage, EditMessage, GuildId, MessageId, ModelError,
+        Permissions,
+    },
+    async_trait,
+};
+use f1_bot_types::Event;
+use serde_json::Value;
+
+use super::Gateway;
+use crate::error::Result;
+
+/// Something the bot sent to Discord, message bodies as the JSON serenity
+/// would have sent.
+#[derive(Debug, Clone)]
+pub enum Outbound {
+    Thread {
+        channel_id: ChannelId,
+        thread_id: ChannelId,
+        name: String,
+    },
+    Message {
+        channel_id: ChannelId,
+        message_id: MessageId,
+        body: Value,
+    },
+    Edit {
+        channel_id: ChannelId,
+        message_id: MessageId,
+        body: Value,
+    },
+    AllowRequest {
+        event_id: u64,
+        request_id: i64,
+    },
+    GuildNotice {
+        guild_id: GuildId,
+        body: Value,
+    },
+}
+
+/// How a simulated request fails, classified the same way real errors are
+/// by [`crate::error::Error::class`].
+#[derive(Debug, Clone, Copy)]
+pub enum Failure {
+    /// An outage, worth retrying.
+    Transient,
+    /// The bot lost access to the channel.
+    Permanent,
+}
+
+impl Failure {
+    fn error(self) -> crate::error::Error {
+        match self {
+            Failure::Transient => {
+                ::serenity::Error::Io(std::io::Error::other("simulated outage"))
+                    .into()
+            },
+            Failure::Permanent => {
+                ::serenity::Error::Model(ModelError::InvalidPermissions {
+                    required: Permissions::SEND_MESSAGES,
+                    present: Permissions::empty(),
+                })
+                .into()
+            },
+        }
+    }
+}
+
+/// [`Gateway`] that records everything instead of sending it, with
+/// failures that can be switched on per channel.
+#[derive(Default)]
+pub struct RecordingGateway {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    last_id: u64,
+    outbound: Vec<Outbound>,
+    failing: HashMap<ChannelId, Failure>,
+}
+
+impl State {
+    /// Snowflakes start high enough to never collide with the ids a test
+    /// picks for its channels.
+    fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        (1 << 40) | self.last_id
+    }
+
+    fn check(
+        &self,
+        channel_id: ChannelId,
+    ) -> Result {
+        match self.failing.get(&channel_id) {
+            Some(failure) => Err(failure.error()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl RecordingGateway {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Makes every request to the channel fail until [`Self::recover`].
+    pub fn fail(
+        &self,
+        channel_id: ChannelId,
+        failure: Failure,
+    ) {
+        self.state().failing.insert(channel_id, failure);
+    }
+
+    pub fn recover(
+        &self,
+        channel_id: ChannelId,
+    ) {
+        self.state().failing.remove(&channel_id);
+    }
+
+    /// Everything sent so far, in order.
+    pub fn outbound(&self) -> Vec<Outbound> {
+        self.state().outbound.clone()
+    }
+
+    /// Bodies of the messages sent to the channel or thread, in order.
+    pub fn messages_in(
+        &self,
+        channel_id: ChannelId,
+    ) -> Vec<Value> {
+        self.state()
+            .outbound
+            .iter()
+            .filter_map(|f| match f {
+                Outbound::Message {
+                    channel_id: sent_to,
+                    body,
+                    ..
+                } if *sent_to == channel_id => Some(body.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Threads opened in the channel.
+    pub fn threads_in(
+        &self,
+        channel_id: ChannelId,
+    ) -> Vec<ChannelId> {
+        self.state()
+            .outbound
+            .iter()
+            .filter_map(|f| match f {
+                Outbound::Thread {
+                    channel_id: parent,
+                    thread_id,
+                    ..
+                } if *parent == channel_id => Some(*thread_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Forgets what was sent, failures stay switched on.
+    pub fn clear(&self) {
+        self.state().outbound.clear();
+    }
+}
+
+#[async_trait]
+impl Gateway for RecordingGateway {
+    async fn create_thread(
+        &self,
+        channel_id: ChannelId,
+        name: String,
+    ) -> Result<ChannelId> {
+        let mut state = self.state();
+        state.check(channel_id)?;
+        let thread_id = ChannelId::new(state.next_id());
+        state.outbound.push(Outbound::Thread {
+            channel_id,
+            thread_id,
+            name,
+        });
+        Ok(thread_id)
+    }
+
+    async fn send_message(
+        &self,
+        channel_id: ChannelId,
+        message: CreateMessage,
+    ) -> Result<MessageId> {
+        let mut state = self.state();
+        state.check(channel_id)?;
+        let message_id = MessageId::new(state.next_id());
+        state.outbound.push(Outbound::Message {
+            channel_id,
+            message_id,
+            body: serde_json::to_value(&message).unwrap_or_default(),
+        });
+        Ok(message_id)
+    }
+
+    async fn edit_message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        message: EditMessage,
+    ) -> Result {
+        let mut state = self.state();
+        state.check(channel_id)?;
+        state.outbound.push(Outbound::Edit {
+            channel_id,
+            message_id,
+            body: serde_json::to_value(&message).unwrap_or_default(),
+        });
+        Ok(())
+    }
+
+    async fn send_allow_request(
+        &self,
+        event: &Event,
+        request_id: i64,
+    ) -> Result<MessageId> {
+        let mut state = self.state();
+        let message_id = MessageId::new(state.next_id());
+        state.outbound.push(Outbound::AllowRequest {
+            event_id: event.id,
+            request_id,
+        });
+        Ok(message_id)
+    }
+
+    async fn notify_guild(
+        &self,
+        guild_id: GuildId,
+        message: CreateMessage,
+    ) -> Result {
+        self.state().outbound.push(Outbound::GuildNotice {
+            guild_id,
+            body: serde_json::to_value(&message).unwrap_or_default(),
+        });
+        Ok(())
+    }
+
+    async fn channel_backoff(
+        &self,
+        _channel_id: ChannelId,
+    ) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use ::serenity::{
+    all::{
+        AutoArchiveDuration, ButtonStyle, Cache, ChannelId, ChannelType,
+        Context, CreateActionRow, CreateButton, CreateEmbed, CreateMessage,
+        CreateThread, EditMessage, GuildId, MessageId,
+    },
+    async_trait,
+    http::{Http, Route},
+};
+use f1_bot_types::Event;
+
+use tracing::warn;
+
+use super::Gateway;
+use crate::{config, error::Result, metrics};
+
+/// [`Gateway`] talking to Discord through serenity.
+#[derive(Clone)]
+pub struct SerenityGateway {
+    cache: Arc<Cache>,
+    http: Arc<Http>,
+}
+
+impl SerenityGateway {
+    pub fn new(ctx: &Context) -> Self {
+        Self {
+            cache: ctx.cache.clone(),
+            http: ctx.http.clone(),
+        }
+    }
+
+    fn cache_http(&self) -> (&Arc<Cache>, &Http) {
+        (&self.cache, &self.http)
+    }
+}
+
+#[async_trait]
+impl Gateway for SerenityGateway {
+    async fn create_thread(
+        &self,
+        channel_id: ChannelId,
+        name: String,
+    ) -> Result<ChannelId> {
+        let thread = timed(
+            channel_id.create_thread(
+                self.cache_http(),
+                CreateThread::new(name)
+                    .auto_archive_duration(AutoArchiveDuration::ThreeDays)
+                    .kind(ChannelType::PublicThread)
+                    .audit_log_reason("New Approved FIA Event"),
+            ),
+        )
+        .await?;
+        Ok(thread.id)
+    }
+
+    async fn send_message(
+        &self,
+        channel_id: ChannelId,
+        message: CreateMessage,
+    ) -> Result<MessageId> {
+        let sent =
+            timed(channel_id.send_message(self.cache_http(), message)).await?;
+        Ok(sent.id)
+    }
+
+    async fn edit_message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        message: EditMessage,
+    ) -> Result {
+        timed(channel_id.edit_message(self.cache_http(), message_id, message))
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn send_allow_request(
+        &self,
+        event: &Event,
+        request_id: i64,
+    ) -> Result<MessageId> {
+        let discord = &config::get().discord;
+        let mut message = CreateMessage::new();
+        let mentions = discord.request_mentions();
+        if !mentions.is_empty() {
+            message = message.content(mentions);
+        }
+        let message = message
+            .embed(
+                CreateEmbed::new().title("New Event Found!").description(
+                    format!(
+                        "## {} {} {}\n\nPlease allow or deny.\n\nEvent Information: ```ron{event:#?}```",
+                        event.year, event.series, event.title,
+                    ),
+                ),
+            )
+            .components(vec![CreateActionRow::Buttons(vec![
+                CreateButton::new(format!("allow-{request_id}"))
+                    .label("Allow")
+                    .style(ButtonStyle::Success),
+                CreateButton::new(format!("deny-{request_id}"))
+                    .label("Deny")
+                    .style(ButtonStyle::Danger),
+            ])]);
+        self.send_message(ChannelId::new(discord.request_channel_id), message)
+            .await
+    }
+
+    async fn notify_guild(
+        &self,
+        guild_id: GuildId,
+        message: CreateMessage,
+    ) -> Result {
+        let partial =
+            timed(guild_id.to_partial_guild(self.cache_http())).await?;
+        let owner = partial.owner_id;
+        if timed(owner.direct_message(self.cache_http(), message.clone()))
+            .await
+            .is_ok()
+        {
+            metrics::GUILD_NOTICES.with_label_values(&["owner"]).inc();
+            return Ok(());
+        }
+        if let Some(system_channel) = partial.system_channel_id {
+            let sent = timed(
+                system_channel.send_message(self.cache_http(), message.clone()),
+            )
+            .await;
+            if sent.is_ok() {
+                metrics::GUILD_NOTICES
+                    .with_label_values(&["system_channel"])
+                    .inc();
+                return Ok(());
+            }
+        }
+        if let Some(fallback) = config::get().discord.notice_fallback_channel_id
+        {
+            timed(
+                ChannelId::new(fallback)
+                    .send_message(self.cache_http(), message),
+            )
+            .await?;
+            metrics::GUILD_NOTICES.with_label_values(&["fallback"]).inc();
+            return Ok(());
+        }
+        metrics::GUILD_NOTICES.with_label_values(&["unreachable"]).inc();
+        warn!(%guild_id, "Nobody could be notified, no fallback channel set");
+        Ok(())
+    }
+
+    async fn channel_backoff(
+        &self,
+        channel_id: ChannelId,
+    ) -> Option<Duration> {
+        let ratelimiter = self.http.ratelimiter.as_ref()?;
+        let routes = ratelimiter.routes();
+        let routes = routes.read().await;
+        let bucket = Route::ChannelMessages {
+            channel_id,
+        }
+        .ratelimiting_bucket();
+        let ratelimit = routes.get(&bucket)?.lock().await;
+        if ratelimit.remaining() > 0 {
+            return None;
+        }
+        ratelimit.reset()?.duration_since(SystemTime::now()).ok()
+    }
+}
+
+/// Awaits a request to Discord, recording how long it took.
+async fn timed<T>(request: impl Future<Output = T>) -> T {
+    let started = Instant::now();
+    let response = request.await;
+    metrics::DISCORD_HTTP_DURATION.observe(started.elapsed().as_secs_f64());
+    response
+}
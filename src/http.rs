@@ -0,0 +1,165 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    Json, Router,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+    routing::{get, post},
+};
+use chrono::{TimeDelta, Utc};
+use serde::Serialize;
+use serenity::all::{ConnectionStage, ShardManager};
+
+use crate::{
+    config, metrics,
+    runner::{heartbeat::Heartbeat, scheduler::Scheduler, wakeup::Wakeup},
+};
+
+pub struct AppState {
+    pub wakeup: Arc<Wakeup>,
+    pub heartbeat: Arc<Heartbeat>,
+    pub scheduler: Arc<Scheduler>,
+    pub shard_manager: Arc<ShardManager>,
+    /// Whether there is a remote database whose sync can fall behind.
+    pub replicated: bool,
+}
+
+#[derive(Serialize)]
+struct Status {
+    healthy: bool,
+    ready: bool,
+    shards: Vec<ShardStatus>,
+    last_tick: Option<String>,
+    last_sync: Option<String>,
+    queue_depth: usize,
+}
+
+#[derive(Serialize)]
+struct ShardStatus {
+    id: u32,
+    stage: String,
+    latency_ms: Option<u128>,
+}
+
+/// Serves the ingest hook, letting whatever writes new events and documents
+/// wake the runner up right away with a `POST /wake`, the Prometheus
+/// metrics on `GET /metrics` and the `/healthz` and `/readyz` probes.
+///
+/// Waking forces a replica sync and a tick, so `/wake` needs
+/// `http.wake_token` as a bearer token, or comes from loopback if no token
+/// is set.
+pub async fn serve(
+    addr: SocketAddr,
+    state: AppState,
+) -> std::io::Result<()> {
+    let app = Router::new()
+        .route("/wake", post(wake))
+        .route("/metrics", get(render_metrics))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(Arc::new(state));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+}
+
+async fn wake(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> StatusCode {
+    let allowed = match &config::get().http.wake_token {
+        Some(token) => headers
+            .get(AUTHORIZATION)
+            .and_then(|f| f.to_str().ok())
+            .and_then(|f| f.strip_prefix("Bearer "))
+            .is_some_and(|f| constant_time_eq(f.as_bytes(), token.as_bytes())),
+        None => peer.ip().is_loopback(),
+    };
+    if !allowed {
+        return StatusCode::UNAUTHORIZED;
+    }
+    state.wakeup.notify();
+    StatusCode::NO_CONTENT
+}
+
+/// Compares without bailing out at the first difference, so the time taken
+/// doesn't tell how much of the token was right.
+fn constant_time_eq(
+    a: &[u8],
+    b: &[u8],
+) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn render_metrics() -> String {
+    metrics::render()
+}
+
+/// Fails once the runner stopped ticking, restarting is the only way out of
+/// that.
+async fn healthz(
+    State(state): State<Arc<AppState>>
+) -> (StatusCode, Json<Status>) {
+    let status = status(&state).await;
+    (status_code(status.healthy), Json(status))
+}
+
+/// Fails while the bot can't do its job right now: shards not connected, no
+/// tick yet or a replica that stopped syncing.
+async fn readyz(
+    State(state): State<Arc<AppState>>
+) -> (StatusCode, Json<Status>) {
+    let status = status(&state).await;
+    (status_code(status.ready), Json(status))
+}
+
+fn status_code(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+async fn status(state: &AppState) -> Status {
+    let now = Utc::now();
+    let config = config::get();
+    let stall_after = TimeDelta::seconds(config.runner.stall_after_secs as i64);
+    let sync_stale_after =
+        TimeDelta::seconds(3 * config.database.sync_interval_secs as i64);
+
+    let runners = state.shard_manager.runners.lock().await;
+    let shards_connected = !runners.is_empty()
+        && runners
+            .values()
+            .all(|f| matches!(f.stage, ConnectionStage::Connected));
+    let shards = runners
+        .iter()
+        .map(|(id, info)| ShardStatus {
+            id: id.0,
+            stage: info.stage.to_string(),
+            latency_ms: info.latency.map(|f| f.as_millis()),
+        })
+        .collect();
+    drop(runners);
+
+    let last_tick = state.heartbeat.last_tick();
+    let last_sync = state.heartbeat.last_sync();
+    let healthy = !state.heartbeat.is_stalled(now, stall_after);
+    let synced = !state.replicated
+        || last_sync.is_some_and(|f| now - f < sync_stale_after);
+
+    Status {
+        healthy,
+        ready: healthy && shards_connected && last_tick.is_some() && synced,
+        shards,
+        last_tick: last_tick.map(|f| f.to_rfc3339()),
+        last_sync: last_sync.map(|f| f.to_rfc3339()),
+        queue_depth: state.scheduler.stats().queue_depth,
+    }
+}
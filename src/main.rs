@@ -1,9 +1,14 @@
-use std::{
-    sync::{Arc, atomic::AtomicBool},
-    time::Duration,
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
 };
 
+use config::{DatabaseConfig, DatabaseMode};
 use event_manager::BotEvents;
+use runner::{
+    guild_cache::GuildCache, heartbeat::Heartbeat, scheduler::Scheduler,
+    shutdown::Shutdown, wakeup::Wakeup,
+};
 
 use sentry::{Hub, SentryFutureExt};
 use serenity::{
@@ -11,18 +16,23 @@ use serenity::{
     client::ClientBuilder,
     prelude::*,
 };
+use storage::LibsqlStorage;
 
-use tracing::{Level, error, level_filters::LevelFilter};
-use tracing_subscriber::{
-    Layer, layer::SubscriberExt, util::SubscriberInitExt,
-};
+use tracing::{error, info, warn};
 
 mod commands;
+mod config;
 mod database;
 mod error;
 mod event_manager;
+mod gateway;
+mod http;
+mod metrics;
+mod migrations;
 mod model;
 mod runner;
+mod storage;
+mod telemetry;
 
 pub struct ShardManagerBox;
 
@@ -32,21 +42,15 @@ impl TypeMapKey for ShardManagerBox {
 
 fn main() {
     _ = dotenvy::dotenv();
-    let guard = sentry::init((
-        std::env::var("SENTRY_DSN").expect("Sentry DSN not found!"),
-        sentry::ClientOptions {
-            release: sentry::release_name!(),
-            traces_sample_rate: 1.0,
-            ..Default::default()
-        },
-    ));
-    sentry::start_session();
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_filter(LevelFilter::from_level(Level::INFO)),
-        )
-        .init();
+    // Logging isn't set up yet, it depends on the configuration.
+    if let Err(why) = config::init() {
+        eprintln!("{why}");
+        std::process::exit(1);
+    }
+    let config = config::get();
+    let telemetry = telemetry::init(&config.telemetry);
+    metrics::init();
+    let stalled = Arc::new(AtomicBool::new(false));
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
@@ -54,27 +58,50 @@ fn main() {
         .block_on(async {
             let discord_token =
                 std::env::var("DISCORD_TOKEN").expect("Discord token empty");
-            let db_token =
-                std::env::var("DATABASE_TOKEN").expect("db_token empty");
-            let db_url =
-                std::env::var("DATABASE_URL").expect("Database url empty");
-            let db_client = libsql::Builder::new_remote_replica(
-                "./local.db",
-                db_url,
-                db_token,
-            )
-            .sync_interval(Duration::from_secs(60))
-            .build()
-            .await
-            .unwrap();
-
-            db_client.sync().await.unwrap();
+            let db_client = match open_database(&config.database).await {
+                Ok(db_client) => Arc::new(db_client),
+                Err(why) => {
+                    error!("Error opening database: {why}");
+                    return;
+                },
+            };
+            let replicated =
+                config.database.mode == DatabaseMode::RemoteReplica;
+
+            let wakeup = Arc::new(Wakeup::default());
+            let heartbeat = Arc::new(Heartbeat::default());
+            if replicated {
+                if let Err(why) = db_client.sync().await {
+                    error!("Error syncing Database: {why}");
+                    return;
+                }
+                heartbeat.synced();
+                tokio::spawn(sync_replica(
+                    db_client.clone(),
+                    wakeup.clone(),
+                    heartbeat.clone(),
+                ));
+            }
+
+            let scheduler =
+                Arc::new(Scheduler::new(config.runner.delivery_concurrency));
+            let shutdown = Arc::new(Shutdown::default());
 
             let conn = db_client.connect().unwrap();
+            if let Err(why) = migrations::run(&conn).await {
+                error!("Error migrating database: {why}");
+                return;
+            }
 
             let event_manager = BotEvents {
                 thread_lock: AtomicBool::new(false),
-                conn: Box::leak(Box::new(conn)),
+                storage: Arc::new(LibsqlStorage::new(conn)),
+                wakeup: wakeup.clone(),
+                scheduler: scheduler.clone(),
+                guild_cache: Arc::new(GuildCache::default()),
+                shutdown: shutdown.clone(),
+                heartbeat: heartbeat.clone(),
+                poll_interval: config.runner.poll_interval(),
             };
             let mut settings = Settings::default();
             settings.cache_users = false;
@@ -93,16 +120,45 @@ fn main() {
                     },
                 };
 
+            if let Some(addr) = config.http.addr {
+                let state = http::AppState {
+                    wakeup: wakeup.clone(),
+                    heartbeat: heartbeat.clone(),
+                    scheduler: scheduler.clone(),
+                    shard_manager: client.shard_manager.clone(),
+                    replicated,
+                };
+                tokio::spawn(async move {
+                    if let Err(why) = http::serve(addr, state).await {
+                        error!("Error serving HTTP: {why}");
+                    }
+                });
+            }
+
             {
                 let mut data = client.data.write().await;
                 data.insert::<ShardManagerBox>(client.shard_manager.clone());
+                data.insert::<Wakeup>(wakeup);
+                data.insert::<Scheduler>(scheduler);
+                data.insert::<Shutdown>(shutdown.clone());
+                data.insert::<Heartbeat>(heartbeat.clone());
             }
 
+            tokio::spawn(watchdog(
+                heartbeat,
+                shutdown.clone(),
+                stalled.clone(),
+            ));
             let shard_manager = client.shard_manager.clone();
             tokio::spawn(async move {
-                tokio::signal::ctrl_c()
-                    .await
-                    .expect("Error registering ctrlc handler");
+                tokio::select! {
+                    _ = shutdown_signal() => shutdown.request(),
+                    _ = shutdown.requested() => {},
+                }
+                info!("Shutting down, draining in-flight deliveries");
+                if !shutdown.drained(config.runner.shutdown_deadline()).await {
+                    warn!("Runner didn't stop in time, stopping shards anyway");
+                }
                 shard_manager.shutdown_all().await;
             });
             let hub = Hub::new_from_top(Hub::current());
@@ -111,10 +167,119 @@ fn main() {
             }
 
             // Final sync once the bot stops
-            if let Err(why) = db_client.sync().await {
-                error!("Error syncing Database: {why:#?}");
+            if replicated {
+                if let Err(why) = db_client.sync().await {
+                    error!("Error syncing Database: {why:#?}");
+                }
             }
         });
-    sentry::end_session();
-    drop(guard);
+    telemetry.shutdown();
+    if stalled.load(Ordering::Relaxed) {
+        std::process::exit(1);
+    }
+}
+
+/// Shuts the bot down once the runner stalled, the same condition that
+/// fails `/healthz`. Docker doesn't restart unhealthy containers, exiting
+/// leaves that to the restart policy.
+async fn watchdog(
+    heartbeat: Arc<Heartbeat>,
+    shutdown: Arc<Shutdown>,
+    stalled: Arc<AtomicBool>,
+) {
+    let runner = &config::get().runner;
+    let stall_after =
+        chrono::TimeDelta::seconds(runner.stall_after_secs as i64);
+    let mut interval = tokio::time::interval(runner.poll_interval());
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.requested() => return,
+        }
+        if heartbeat.is_stalled(chrono::Utc::now(), stall_after) {
+            error!(
+                last_tick = ?heartbeat.last_tick(),
+                "Runner stalled, shutting down to get restarted"
+            );
+            stalled.store(true, Ordering::Relaxed);
+            shutdown.request();
+            return;
+        }
+    }
+}
+
+async fn open_database(
+    config: &DatabaseConfig
+) -> error::Result<libsql::Database> {
+    let db_client = match config.mode {
+        DatabaseMode::RemoteReplica => {
+            let (Ok(db_url), Ok(db_token)) = (
+                std::env::var("DATABASE_URL"),
+                std::env::var("DATABASE_TOKEN"),
+            ) else {
+                return Err(error::Error::Config(
+                    "DATABASE_URL and DATABASE_TOKEN must be set for the \
+                    remote-replica database mode"
+                        .to_owned(),
+                ));
+            };
+            libsql::Builder::new_remote_replica(&config.path, db_url, db_token)
+                .build()
+                .await?
+        },
+        DatabaseMode::Local => {
+            libsql::Builder::new_local(&config.path).build().await?
+        },
+        DatabaseMode::Memory => {
+            libsql::Builder::new_local(":memory:").build().await?
+        },
+    };
+    Ok(db_client)
+}
+
+/// Resolves on Ctrl-C or, on unix, SIGTERM as sent by `docker stop`.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(
+            tokio::signal::unix::SignalKind::terminate(),
+        )
+        .expect("Error registering SIGTERM handler")
+        .recv()
+        .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            result.expect("Error registering ctrlc handler");
+        },
+        _ = terminate => {},
+    }
+}
+
+/// Pulls changes from the remote database and wakes the runner up whenever
+/// something new arrived.
+async fn sync_replica(
+    db_client: Arc<libsql::Database>,
+    wakeup: Arc<Wakeup>,
+    heartbeat: Arc<Heartbeat>,
+) {
+    let mut interval =
+        tokio::time::interval(config::get().database.sync_interval());
+    // The first tick completes right away, the initial sync already happened.
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match db_client.sync().await {
+            Ok(replicated) => {
+                heartbeat.synced();
+                if replicated.frames_synced() > 0 {
+                    wakeup.notify();
+                }
+            },
+            Err(why) => error!("Error syncing Database: {why}"),
+        }
+    }
 }
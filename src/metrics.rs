@@ -0,0 +1,184 @@
+use std::{sync::LazyLock, time::Instant};
+
+use chrono::{DateTime, Utc};
+use prometheus::{
+    Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec,
+    TextEncoder, exponential_buckets, register_histogram,
+    register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge_vec,
+};
+use tracing::{Subscriber, span};
+use tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+
+pub static TICK_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "runner_tick_duration_seconds",
+        "Time a single runner tick took",
+        exponential_buckets(0.05, 2.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
+pub static EVENTS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "events",
+        "Events of the current year the runner looked at, by status",
+        &["status"]
+    )
+    .unwrap()
+});
+
+pub static DOCUMENTS_POSTED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "documents_posted_total",
+        "Documents every subscribed guild got a final delivery for"
+    )
+    .unwrap()
+});
+
+pub static DELIVERIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "deliveries_total",
+        "Delivery attempts by outcome and error class (transient, user or \
+        internal, none when sent)",
+        &["outcome", "error_class"]
+    )
+    .unwrap()
+});
+
+pub static GUILD_NOTICES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "guild_notices_total",
+        "Notices about disabled destinations, by where they ended up",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+pub static THREADS_CREATED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("threads_created_total", "Event threads created")
+        .unwrap()
+});
+
+pub static APPROVAL_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "approval_latency_seconds",
+        "Time from posting an approval request to someone answering it",
+        &["response"],
+        vec![
+            60.0, 300.0, 900.0, 1800.0, 3600.0, 10800.0, 43200.0, 86400.0,
+            259200.0
+        ]
+    )
+    .unwrap()
+});
+
+pub static DISCORD_HTTP_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "discord_http_request_duration_seconds",
+        "Latency of requests to the Discord API, rate limit waits included"
+    )
+    .unwrap()
+});
+
+pub static DB_QUERY_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "db_query_duration_seconds",
+        "Latency of the instrumented libsql queries",
+        &["query"],
+        exponential_buckets(0.001, 2.0, 14).unwrap()
+    )
+    .unwrap()
+});
+
+/// Registers every metric up front, so they show up before they're first
+/// touched.
+pub fn init() {
+    LazyLock::force(&TICK_DURATION);
+    LazyLock::force(&EVENTS);
+    LazyLock::force(&DOCUMENTS_POSTED);
+    LazyLock::force(&DELIVERIES);
+    LazyLock::force(&GUILD_NOTICES);
+    LazyLock::force(&THREADS_CREATED);
+    LazyLock::force(&APPROVAL_LATENCY);
+    LazyLock::force(&DISCORD_HTTP_DURATION);
+    LazyLock::force(&DB_QUERY_DURATION);
+}
+
+/// Everything in the Prometheus text format.
+pub fn render() -> String {
+    let mut buffer = vec![];
+    if let Err(why) =
+        TextEncoder::new().encode(&prometheus::gather(), &mut buffer)
+    {
+        tracing::error!("Error encoding metrics: {why}");
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+pub fn observe_approval(
+    requested_at: DateTime<Utc>,
+    response: &str,
+) {
+    let latency = (Utc::now() - requested_at).num_milliseconds().max(0);
+    APPROVAL_LATENCY
+        .with_label_values(&[response])
+        .observe(latency as f64 / 1000.0);
+}
+
+struct SpanStart(Instant);
+
+/// Turns the durations of existing spans into histograms: the runner tick
+/// and the instrumented queries in `database.rs`.
+pub struct MetricsLayer;
+
+impl MetricsLayer {
+    fn is_tracked(metadata: &tracing::Metadata<'_>) -> bool {
+        let target = metadata.target();
+        target.starts_with("fia_docs_bot::database")
+            || (target == "fia_docs_bot::runner" && metadata.name() == "tick")
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        _attrs: &span::Attributes<'_>,
+        id: &span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if Self::is_tracked(span.metadata()) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(
+        &self,
+        id: span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(elapsed) =
+            span.extensions().get::<SpanStart>().map(|f| f.0.elapsed())
+        else {
+            return;
+        };
+        let seconds = elapsed.as_secs_f64();
+        let metadata = span.metadata();
+        if metadata.target().starts_with("fia_docs_bot::database") {
+            DB_QUERY_DURATION
+                .with_label_values(&[metadata.name()])
+                .observe(seconds);
+        } else {
+            TICK_DURATION.observe(seconds);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::pin::Pin;
+
+use f1_bot_types::Series;
+use libsql::Connection;
+use tracing::info;
+
+use crate::{
+    error::{Error, Result},
+    model::guild::series_key,
+};
+
+/// Runs after the SQL of a migration, in the same transaction.
+type Hook = for<'a> fn(
+    &'a Connection,
+) -> Pin<Box<dyn Future<Output = Result> + Send + 'a>>;
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+    post: Option<Hook>,
+}
+
+/// Every schema change, in order. Never edit a migration that shipped, add a
+/// new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../migrations/0001_initial.sql"),
+        post: Some(adopt_legacy_schema),
+    },
+    Migration {
+        version: 2,
+        name: "document categories",
+        sql: include_str!("../migrations/0002_document_categories.sql"),
+        post: None,
+    },
+];
+
+const SCHEMA_VERSION_TABLE: &str = r#"CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);"#;
+
+/// Column prefixes the per-series settings used to have on `guilds`.
+const LEGACY_SERIES_COLUMNS: [(Series, &str); 4] = [
+    (Series::F1, "f1"),
+    (Series::F2, "f2"),
+    (Series::F3, "f3"),
+    (Series::F1Academy, "f1a"),
+];
+
+/// Brings the database up to the schema this binary was built for.
+///
+/// Each migration runs in its own transaction together with bumping
+/// `schema_version`. Refuses to touch a database that is newer than the
+/// binary, an old build would otherwise run against tables it doesn't know.
+pub async fn run(db_conn: &Connection) -> Result {
+    db_conn.execute_batch(SCHEMA_VERSION_TABLE).await?;
+    let current = current_version(db_conn).await?;
+    let supported = MIGRATIONS.last().map_or(0, |f| f.version);
+    if current > supported {
+        return Err(Error::SchemaTooNew {
+            found: current,
+            supported,
+        });
+    }
+
+    for migration in MIGRATIONS.iter().filter(|f| f.version > current) {
+        let tx = db_conn.transaction().await?;
+        tx.execute_batch(migration.sql).await?;
+        if let Some(post) = migration.post {
+            post(&tx).await?;
+        }
+        tx.execute(
+            "INSERT INTO schema_version (version, name) VALUES (?, ?)",
+            libsql::params![migration.version, migration.name],
+        )
+        .await?;
+        tx.commit().await?;
+        info!(
+            version = migration.version,
+            "Applied migration {}", migration.name
+        );
+    }
+    Ok(())
+}
+
+async fn current_version(db_conn: &Connection) -> Result<i64> {
+    let mut cursor = db_conn
+        .query("SELECT COALESCE(MAX(version), 0) FROM schema_version", ())
+        .await?;
+    Ok(match cursor.next().await? {
+        Some(row) => row.get::<i64>(0)?,
+        None => 0,
+    })
+}
+
+/// Databases from before versioned migrations got their tables from
+/// elsewhere and were patched up at startup. Brings their `guilds` table in
+/// line with the initial migration, a no-op on fresh databases.
+fn adopt_legacy_schema(
+    db_conn: &Connection
+) -> Pin<Box<dyn Future<Output = Result> + Send + '_>> {
+    Box::pin(async move {
+        if !has_column(db_conn, "guilds", "left_at").await? {
+            db_conn
+                .execute("ALTER TABLE guilds ADD COLUMN left_at TEXT", ())
+                .await?;
+        }
+        for prefix in ["f1", "f2", "f3", "f1a"] {
+            db_conn
+                .execute(
+                    &format!("DROP INDEX IF EXISTS guilds_{prefix}_subscribed"),
+                    (),
+                )
+                .await?;
+        }
+        // Per-series settings used to be columns on `guilds`.
+        for (series, prefix) in LEGACY_SERIES_COLUMNS {
+            if !has_column(db_conn, "guilds", &format!("{prefix}_channel"))
+                .await?
+            {
+                continue;
+            }
+            let migrated = db_conn
+                .execute(
+                    &format!(
+                        r#"INSERT INTO guild_series_settings
+        (guild_id, series, channel_id, role_id, use_threads)
+    SELECT id, ?, {prefix}_channel, {prefix}_role, {prefix}_threads
+    FROM guilds
+    WHERE {prefix}_channel IS NOT NULL OR {prefix}_role IS NOT NULL
+    ON CONFLICT (guild_id, series) DO NOTHING"#
+                    ),
+                    [series_key(series)],
+                )
+                .await?;
+            for column in ["channel", "role", "threads"] {
+                db_conn
+                    .execute(
+                        &format!(
+                            "ALTER TABLE guilds DROP COLUMN {prefix}_{column}"
+                        ),
+                        (),
+                    )
+                    .await?;
+            }
+            info!(migrated, "Moved {series} settings to guild_series_settings");
+        }
+        Ok(())
+    })
+}
+
+async fn has_column(
+    db_conn: &Connection,
+    table: &str,
+    column: &str,
+) -> Result<bool> {
+    let mut cursor = db_conn
+        .query(
+            &format!(
+                "SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?"
+            ),
+            [column],
+        )
+        .await?;
+    Ok(cursor.next().await?.is_some())
+}
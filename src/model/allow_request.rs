@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use notifbot_macros::notifbot_enum;
+
+notifbot_enum!(AllowRequestStatus {
+    Open,
+    Allowed,
+    Denied
+});
+
+/// Asks the maintainers whether the documents of a newly found event should
+/// be posted.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct AllowRequest {
+    pub id: i64,
+    pub event_id: u64,
+    pub response: AllowRequestStatus,
+    pub created_at: DateTime<Utc>,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<DateTime<Utc>>,
+}
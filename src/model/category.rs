@@ -0,0 +1,112 @@
+use serde::Serialize;
+
+use crate::config;
+
+/// What a document is about, guessed from its title.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentCategory {
+    Decision,
+    Summons,
+    Infringement,
+    Classification,
+    TechnicalReport,
+    EntryList,
+    Other,
+}
+
+impl DocumentCategory {
+    /// Every category, in the order they're shown.
+    pub const ALL: [Self; 7] = [
+        Self::Decision,
+        Self::Summons,
+        Self::Infringement,
+        Self::Classification,
+        Self::TechnicalReport,
+        Self::EntryList,
+        Self::Other,
+    ];
+
+    /// How the category is stored in `documents.category` and
+    /// `guild_series_settings.categories`.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Decision => "decision",
+            Self::Summons => "summons",
+            Self::Infringement => "infringement",
+            Self::Classification => "classification",
+            Self::TechnicalReport => "technical_report",
+            Self::EntryList => "entry_list",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.key() == key)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Decision => "Decision",
+            Self::Summons => "Summons",
+            Self::Infringement => "Infringement",
+            Self::Classification => "Classification",
+            Self::TechnicalReport => "Technical Report",
+            Self::EntryList => "Entry List",
+            Self::Other => "Other",
+        }
+    }
+
+    /// Embed color of the documents in the category.
+    pub fn color(self) -> u32 {
+        let branding = &config::get().branding;
+        let colors = &branding.category_colors;
+        match self {
+            Self::Decision => colors.decision,
+            Self::Summons => colors.summons,
+            Self::Infringement => colors.infringement,
+            Self::Classification => colors.classification,
+            Self::TechnicalReport => colors.technical_report,
+            Self::EntryList => colors.entry_list,
+            Self::Other => branding.embed_color,
+        }
+    }
+
+    /// Guesses the category from the title, the FIA names its documents
+    /// consistently enough for keywords to work. Checked in order, a
+    /// "Summons" title often mentions the infringement it's about.
+    pub fn classify(title: &str) -> Self {
+        let title = title.to_lowercase();
+        let has =
+            |keywords: &[&str]| keywords.iter().any(|f| title.contains(f));
+        if has(&["summons"]) {
+            Self::Summons
+        } else if has(&["infringement"]) {
+            Self::Infringement
+        } else if has(&["decision", "offence"]) {
+            Self::Decision
+        } else if has(&["classification"]) {
+            Self::Classification
+        } else if has(&[
+            "technical report",
+            "technical delegate",
+            "scrutineering",
+        ]) {
+            Self::TechnicalReport
+        } else if has(&["entry list"]) {
+            Self::EntryList
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// Stores a selection of categories as a comma separated list of keys.
+pub fn categories_key(categories: &[DocumentCategory]) -> String {
+    categories.iter().map(|f| f.key()).collect::<Vec<_>>().join(",")
+}
+
+/// Reads what [`categories_key`] stored, unknown keys are skipped.
+pub fn categories_from_key(key: &str) -> Vec<DocumentCategory> {
+    key.split(',').filter_map(DocumentCategory::from_key).collect()
+}
@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use notifbot_macros::notifbot_enum;
+use serde::{Deserialize, Serialize};
+
+notifbot_enum!(DeliveryStatus {
+    Pending,
+    Sent,
+    Failed,
+    DeadLetter
+});
+
+/// A single document going out to a single guild destination.
+///
+/// Rows are created as `Pending` before anything is sent. Transient errors
+/// keep them `Pending` with a later `next_attempt_at`, they only move to a
+/// terminal state (`Sent`, `Failed` or `DeadLetter`) once Discord answered
+/// for good or the retries ran out.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Delivery {
+    pub id: i64,
+    pub document_id: i64,
+    pub guild_id: i64,
+    /// Channel or thread the document is posted in.
+    pub channel_id: String,
+    pub role_id: Option<String>,
+    pub status: DeliveryStatus,
+    pub message_id: Option<String>,
+    pub error: Option<String>,
+    pub attempts: i64,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -1,21 +1,31 @@
 use chrono::{DateTime, Utc};
 use f1_bot_types::Series;
 
+use crate::model::category::DocumentCategory;
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct Guild {
     pub id: i64,
     pub discord_id: String,
     pub name: String,
-    pub f1_role: Option<String>,
-    pub f1_channel: Option<String>,
-    pub f1_threads: bool,
-    pub f2_role: Option<String>,
-    pub f2_channel: Option<String>,
-    pub f2_threads: bool,
-    pub f3_role: Option<String>,
-    pub f3_channel: Option<String>,
-    pub f3_threads: bool,
     pub joined_at: DateTime<Utc>,
+    /// Set once the bot got removed from the guild.
+    pub left_at: Option<DateTime<Utc>>,
+    /// Loaded from `guild_series_settings`, only has the series the guild
+    /// configured at some point.
+    #[serde(skip_deserializing)]
+    pub series_settings: Vec<SeriesSettings>,
+}
+
+/// Where and how documents of a series get posted in a guild.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct SeriesSettings {
+    pub series: Series,
+    pub channel: Option<String>,
+    pub role: Option<String>,
+    pub threads: bool,
+    /// Categories of documents posted, `None` for all of them.
+    pub categories: Option<Vec<DocumentCategory>>,
 }
 
 impl Guild {
@@ -23,21 +33,73 @@ impl Guild {
         &self,
         series: Series,
     ) -> (Option<&String>, Option<&String>, bool) {
-        match series {
-            Series::F1 => self.f1_settings(),
-            Series::F2 => self.f2_settings(),
-            Series::F3 => self.f3_settings(),
-            Series::F1Academy => panic!("F1 Academy unsupported!"),
+        match self
+            .series_settings
+            .iter()
+            .find(|f| series_key(f.series) == series_key(series))
+        {
+            Some(settings) => (
+                settings.role.as_ref(),
+                settings.channel.as_ref(),
+                settings.threads,
+            ),
+            None => (None, None, true),
         }
     }
 
-    pub fn f1_settings(&self) -> (Option<&String>, Option<&String>, bool) {
-        (self.f1_role.as_ref(), self.f1_channel.as_ref(), self.f1_threads)
+    /// Categories of documents the guild wants for the series, `None` if it
+    /// didn't narrow them down.
+    pub fn categories_for_series(
+        &self,
+        series: Series,
+    ) -> Option<&[DocumentCategory]> {
+        self.series_settings
+            .iter()
+            .find(|f| series_key(f.series) == series_key(series))
+            .and_then(|f| f.categories.as_deref())
+    }
+}
+
+/// How a series is stored in `guild_series_settings`.
+pub fn series_key(series: Series) -> &'static str {
+    match series {
+        Series::F1 => "f1",
+        Series::F2 => "f2",
+        Series::F3 => "f3",
+        Series::F1Academy => "f1academy",
+    }
+}
+
+pub fn series_from_key(key: &str) -> Option<Series> {
+    match key {
+        "f1" => Some(Series::F1),
+        "f2" => Some(Series::F2),
+        "f3" => Some(Series::F3),
+        "f1academy" => Some(Series::F1Academy),
+        _ => None,
     }
-    pub fn f2_settings(&self) -> (Option<&String>, Option<&String>, bool) {
-        (self.f2_role.as_ref(), self.f2_channel.as_ref(), self.f2_threads)
+}
+
+/// Serializes a series as its [`series_key`], for the tables that store it
+/// that way.
+pub mod series_as_key {
+    use f1_bot_types::Series;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    use super::{series_from_key, series_key};
+
+    pub fn serialize<S: Serializer>(
+        series: &Series,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(series_key(*series))
     }
-    pub fn f3_settings(&self) -> (Option<&String>, Option<&String>, bool) {
-        (self.f3_role.as_ref(), self.f3_channel.as_ref(), self.f3_threads)
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D
+    ) -> Result<Series, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        series_from_key(&key)
+            .ok_or_else(|| D::Error::custom(format!("unknown series {key}")))
     }
 }
@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use f1_bot_types::Series;
+use serde::{Deserialize, Serialize};
+
+/// Health of the destination a guild configured for a series.
+///
+/// Permanent delivery failures count up `consecutive_failures`, any success
+/// resets it. Once too many failures pile up the destination gets
+/// `disabled_at` and is skipped until the settings are saved again.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DestinationHealth {
+    pub guild_id: i64,
+    #[serde(with = "crate::model::guild::series_as_key")]
+    pub series: Series,
+    pub consecutive_failures: i64,
+    pub last_error: Option<String>,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -1,3 +1,7 @@
+pub mod allow_request;
+pub mod category;
+pub mod delivery;
 pub mod document;
 pub mod guild;
+pub mod health;
 pub mod thread;
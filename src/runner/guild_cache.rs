@@ -0,0 +1,56 @@
+use std::{collections::HashMap, sync::Arc};
+
+use f1_bot_types::Series;
+use tokio::sync::Mutex;
+
+use crate::{model::guild::Guild, storage::Storage};
+
+/// Guilds subscribed to each series, so the runner doesn't have to load
+/// every guild for every event on every tick.
+///
+/// Anything that changes which guilds get a series (`/settings`, joining or
+/// leaving a guild) has to call [`GuildCache::invalidate`].
+#[derive(Debug, Default)]
+pub struct GuildCache {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Bumped on every invalidation, so a load that raced with one doesn't
+    /// put stale guilds back into the cache.
+    generation: u64,
+    by_series: HashMap<String, Arc<Vec<Guild>>>,
+}
+
+impl GuildCache {
+    /// Guilds with a channel set up for the series, loaded from the database
+    /// on the first call after an invalidation.
+    pub async fn for_series(
+        &self,
+        storage: &dyn Storage,
+        series: Series,
+    ) -> crate::error::Result<Arc<Vec<Guild>>> {
+        let key = series.to_string();
+        let generation = {
+            let inner = self.inner.lock().await;
+            if let Some(guilds) = inner.by_series.get(&key) {
+                return Ok(guilds.clone());
+            }
+            inner.generation
+        };
+
+        let guilds = Arc::new(storage.fetch_guilds_for_series(series).await?);
+        let mut inner = self.inner.lock().await;
+        if inner.generation == generation {
+            inner.by_series.insert(key, guilds.clone());
+        }
+        Ok(guilds)
+    }
+
+    pub async fn invalidate(&self) {
+        let mut inner = self.inner.lock().await;
+        inner.generation += 1;
+        inner.by_series.clear();
+    }
+}
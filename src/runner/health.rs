@@ -0,0 +1,68 @@
+use f1_bot_types::Series;
+use serenity::all::{CreateEmbed, CreateMessage, GuildId};
+use tracing::{error, info};
+
+use crate::{
+    commands::set::subcommand_name, config, gateway::Gateway,
+    model::guild::Guild, storage::Storage,
+};
+
+/// Counts a permanent failure for the guild's series destination and
+/// disables it once it keeps failing, letting the guild know about it.
+#[tracing::instrument(skip(storage, gateway, guild), fields(guild_id = guild.id))]
+pub async fn record_failure(
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    guild: &Guild,
+    series: Series,
+    error: &str,
+) -> crate::error::Result {
+    let health =
+        storage.record_destination_failure(guild.id, series, error).await?;
+    if health.consecutive_failures < config::get().runner.disable_after_failures
+        || !storage.disable_destination(guild.id, series).await?
+    {
+        return Ok(());
+    }
+
+    info!(
+        guild_id = guild.id,
+        failures = health.consecutive_failures,
+        "Disabled {series} destination"
+    );
+    if let Err(why) = notify_disabled(gateway, guild, series, error).await {
+        sentry::capture_error(&why);
+        error!("Error notifying guild about disabled destination: {why}");
+    }
+    Ok(())
+}
+
+/// Tells the guild that a destination got disabled, see
+/// [`Gateway::notify_guild`] for who ends up getting it.
+async fn notify_disabled(
+    gateway: &dyn Gateway,
+    guild: &Guild,
+    series: Series,
+    error: &str,
+) -> crate::error::Result {
+    let (_, channel, _) = guild.settings_for_series(series);
+    let channel = channel
+        .map_or("the configured channel".to_owned(), |f| format!("<#{f}>"));
+    let message = CreateMessage::new().embed(
+        CreateEmbed::new()
+            .title("FIA Documents disabled")
+            .description(format!(
+                "Posting {series} documents to {channel} in **{}** failed \
+                {} times in a row, so it was disabled.\n\n\
+                Last error: ```{error}```\n\
+                Fix the channel permissions and run `/settings {}` again to \
+                re-enable it.",
+                guild.name,
+                config::get().runner.disable_after_failures,
+                subcommand_name(series),
+            ))
+            .color(0xFF0000),
+    );
+
+    gateway.notify_guild(GuildId::new(guild.discord_id.parse()?), message).await
+}
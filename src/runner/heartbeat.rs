@@ -0,0 +1,55 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serenity::prelude::TypeMapKey;
+
+/// When the runner and the replica sync last did their job, so the health
+/// endpoints can tell a bot that is alive but stuck.
+#[derive(Debug)]
+pub struct Heartbeat {
+    started: DateTime<Utc>,
+    last_tick: Mutex<Option<DateTime<Utc>>>,
+    last_sync: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl TypeMapKey for Heartbeat {
+    type Value = Arc<Heartbeat>;
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            started: Utc::now(),
+            last_tick: Mutex::new(None),
+            last_sync: Mutex::new(None),
+        }
+    }
+}
+
+impl Heartbeat {
+    pub fn ticked(&self) {
+        *self.last_tick.lock().unwrap() = Some(Utc::now());
+    }
+
+    pub fn synced(&self) {
+        *self.last_sync.lock().unwrap() = Some(Utc::now());
+    }
+
+    pub fn last_tick(&self) -> Option<DateTime<Utc>> {
+        *self.last_tick.lock().unwrap()
+    }
+
+    pub fn last_sync(&self) -> Option<DateTime<Utc>> {
+        *self.last_sync.lock().unwrap()
+    }
+
+    /// Whether the runner didn't finish a tick for `stall_after`. Before the
+    /// first tick the runner gets the same leeway from startup.
+    pub fn is_stalled(
+        &self,
+        now: DateTime<Utc>,
+        stall_after: TimeDelta,
+    ) -> bool {
+        now - self.last_tick().unwrap_or(self.started) >= stall_after
+    }
+}
@@ -1,333 +1,674 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    num::NonZeroU64,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use chrono::{DateTime, Utc};
-use f1_bot_types::{Event, EventStatus};
-use libsql::{Connection, de, params};
-use notifbot_macros::notifbot_enum;
+use chrono::{TimeDelta, Utc};
+use f1_bot_types::{Document, Event, EventStatus, Series};
 use sentry::{
     TransactionContext, User,
     protocol::{SpanStatus, Value},
 };
 use serenity::{
-    all::{
-        ChannelId, Context, CreateActionRow, CreateButton, CreateEmbed,
-        CreateMessage,
-    },
+    all::{ChannelId, CreateMessage},
     futures::future::join_all,
 };
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::database::{
-    create_message, create_new_thread, fetch_docs_for_event,
-    fetch_events_by_status, fetch_guilds, fetch_images_for_document,
-    fetch_thread_for_guild_and_event, mark_doc_done, mark_event_done,
+use crate::{
+    config,
+    database::create_message,
+    error::{Error, ErrorClass},
+    gateway::Gateway,
+    metrics,
+    model::{
+        allow_request::AllowRequest, category::DocumentCategory,
+        delivery::Delivery, guild::Guild, thread::Thread,
+    },
+    runner::{
+        clock::{Clock, SystemClock},
+        guild_cache::GuildCache,
+        heartbeat::Heartbeat,
+        retry::FailureKind,
+        scheduler::Scheduler,
+        shutdown::Shutdown,
+        wakeup::Wakeup,
+    },
+    storage::Storage,
 };
 
-const REQUEST_CHANNEL_ID: u64 = 1151509515066421302;
-
-notifbot_enum!(AllowRequestStatus {
-    Open,
-    Allowed,
-    Denied
-});
-
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct AllowRequest {
-    id: i64,
-    event_id: u64,
-    response: AllowRequestStatus,
-    created_at: DateTime<Utc>,
-    approved_by: Option<String>,
-    approved_at: Option<DateTime<Utc>>,
-}
+pub mod clock;
+pub mod guild_cache;
+pub mod health;
+pub mod heartbeat;
+pub mod retry;
+pub mod scheduler;
+pub mod shutdown;
+pub mod wakeup;
 
-#[tracing::instrument(skip(db_conn))]
-pub async fn has_allow_request(
-    db_conn: &Connection,
-    event: &Event,
-) -> Result<Option<AllowRequest>, crate::error::Error> {
-    let mut rows = db_conn
-        .query("SELECT * FROM allow_requests WHERE event_id = ?", [event.id])
-        .await?;
-    Ok(match rows.next().await?.map(|f| de::from_row::<AllowRequest>(&f)) {
-        Some(r) => Some(r?),
-        None => None,
-    })
-}
+#[cfg(test)]
+mod tests;
+
+/// How often guilds past their retention window get purged.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
-#[tracing::instrument(skip(db_conn))]
+/// Shortest time the runner sleeps between two ticks.
+const MIN_SLEEP: Duration = Duration::from_secs(1);
+
+#[tracing::instrument(skip(storage, gateway))]
 pub async fn create_allow_request(
-    db_conn: &Connection,
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
     event: &Event,
-    ctx: &Context,
 ) -> Result<AllowRequest, crate::error::Error> {
-    db_conn
-        .execute(
-            "INSERT INTO allow_requests (event_id, response) VALUES (?, ?)",
-            params![event.id, AllowRequestStatus::Open.to_str()],
+    let request = storage.insert_allow_request(event.id).await?;
+    gateway.send_allow_request(event, request.id).await?;
+    Ok(request)
+}
+
+/// Opens the thread for the event in the guild's channel for the series and
+/// remembers it.
+#[tracing::instrument(skip(storage, gateway))]
+pub async fn create_new_thread(
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    guild: &Guild,
+    event: &Event,
+) -> crate::error::Result<Thread> {
+    let (_, channel, threads) = guild.settings_for_series(event.series);
+    let Some(channel) = channel else {
+        return Err(Error::InvalidSettings(format!(
+            "no channel set for {}",
+            event.series
+        )));
+    };
+    if !threads {
+        return Err(Error::InvalidSettings(format!(
+            "threads are turned off for {}",
+            event.series
+        )));
+    }
+
+    let Some(channel_id) = parse_channel_id(channel) else {
+        return Err(Error::InvalidSettings(format!(
+            "invalid channel id {channel:?} for {}",
+            event.series
+        )));
+    };
+    let thread_id = gateway
+        .create_thread(
+            channel_id,
+            format!("{} {} {}", event.series, event.year, event.title),
+        )
+        .await?;
+
+    let id = storage
+        .insert_new_thread(
+            &thread_id.to_string(),
+            guild.id,
+            event.id as i64,
+            channel,
         )
         .await?;
-    let row_id = db_conn.last_insert_rowid();
-    create_discord_allow_request(ctx, event, row_id).await?;
-    Ok(AllowRequest {
-        id: row_id,
-        event_id: event.id,
-        response: AllowRequestStatus::Open,
+
+    Ok(Thread {
+        id,
+        guild_id: guild.id,
+        event_id: event.id as i64,
+        channel_id: channel.to_string(),
+        discord_id: thread_id.to_string(),
         created_at: Utc::now(),
-        approved_by: None,
-        approved_at: None,
     })
 }
 
-#[tracing::instrument]
-pub async fn create_discord_allow_request(
-    ctx: &Context,
+/// Channel or thread the documents of the event go to in the guild, opening
+/// the thread for the event if the guild wants threads and there is none
+/// yet. `None` if the guild has no channel set up for the series.
+async fn destination(
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    scheduler: &Scheduler,
+    guild: &Guild,
     event: &Event,
-    request_id: i64,
-) -> Result<(), crate::error::Error> {
-    ChannelId::new(REQUEST_CHANNEL_ID)
-        .send_message(
-            ctx,
-            CreateMessage::new()
-                .content("<@142951266811641856> & <@&738665034359767060>")
-                .embed(
-                    CreateEmbed::new().title("New Event Found!").description(
-                        format!(
-                            "## {} {} {}\n\nPlease allow or deny.\n\nEvent Information: ```ron{event:#?}```",
-                            event.year, event.series, event.title,
-                        ),
-                    ),
-                )
-                .components(vec![CreateActionRow::Buttons(vec![
-                    CreateButton::new(format!("allow-{request_id}"))
-                        .label("Allow")
-                        .style(serenity::all::ButtonStyle::Success),
-                    CreateButton::new(format!("deny-{request_id}"))
-                        .label("Deny")
-                        .style(serenity::all::ButtonStyle::Danger),
-                ])]),
-        )
-        .await?;
-    Ok(())
+) -> crate::error::Result<Option<String>> {
+    let (_, Some(channel), use_threads) =
+        guild.settings_for_series(event.series)
+    else {
+        return Ok(None);
+    };
+    if !use_threads {
+        return Ok(Some(channel.to_owned()));
+    }
+    if let Some(thread) = storage
+        .fetch_thread_for_guild_and_event(guild.id, event.id as i64)
+        .await?
+    {
+        return Ok(Some(thread.discord_id));
+    }
+    let _permit = scheduler.acquire(gateway, None).await;
+    let thread = create_new_thread(storage, gateway, guild, event).await?;
+    metrics::THREADS_CREATED.inc();
+    storage.reset_destination_health(guild.id, event.series).await?;
+    Ok(Some(thread.discord_id))
+}
+
+/// Channel id as stored in the database, `None` if it isn't a snowflake.
+fn parse_channel_id(raw: &str) -> Option<ChannelId> {
+    raw.parse().ok().and_then(NonZeroU64::new).map(ChannelId::from)
+}
+
+/// Pings the notify role of the guild with the message, if it set one.
+pub fn with_role_mention(
+    message: CreateMessage,
+    role: Option<&str>,
+) -> CreateMessage {
+    match role {
+        Some(role) => message.content(format!("<@&{role}>")),
+        None => message,
+    }
 }
 
 pub async fn runner(
-    db_conn: &Connection,
-    ctx: &Context,
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    wakeup: &Wakeup,
+    scheduler: &Scheduler,
+    guild_cache: &GuildCache,
+    shutdown: &Shutdown,
+    heartbeat: &Heartbeat,
+    poll_interval: Duration,
 ) -> Result<(), crate::error::Error> {
     info!("Runner running");
-    loop {
-        let transaction = sentry::start_transaction(TransactionContext::new(
-            "runner",
-            "main-task",
-        ));
-
-        let child = transaction.start_child("db", "Fetch Events");
-        child.set_data("status", Value::String(EventStatus::NotAllowed.into()));
-        let not_allowed_events =
-            fetch_events_by_status(db_conn, EventStatus::NotAllowed).await?;
-
-        child.set_status(SpanStatus::Ok);
-        child.finish();
-
-        for event in not_allowed_events.into_iter() {
-            let span = transaction.start_child("db", "Has Allow Requests");
-            if has_allow_request(db_conn, &event).await?.is_none() {
-                let ca_span = span.start_child("db", "Create Allow Request");
-                ca_span.set_data(
-                    "allow-request",
-                    serde_json::to_value(&event).unwrap(),
-                );
-                create_allow_request(db_conn, &event, ctx).await?;
-                ca_span.set_status(SpanStatus::Ok);
-                ca_span.finish();
-            }
-            span.set_status(SpanStatus::Ok);
-            span.finish();
+    let _guard = shutdown.track_runner();
+    let clock = SystemClock;
+    let mut last_purge: Option<Instant> = None;
+    while !shutdown.is_requested() {
+        tick(
+            storage,
+            gateway,
+            &clock,
+            scheduler,
+            guild_cache,
+            shutdown,
+            &mut last_purge,
+        )
+        .await?;
+        heartbeat.ticked();
+
+        // Sleep until something signals new work, the next retry is due or
+        // the fallback poll interval passed, whichever comes first.
+        let timeout = match storage.fetch_next_attempt_at().await? {
+            Some(next) => (next - clock.now())
+                .to_std()
+                .unwrap_or_default()
+                .min(poll_interval)
+                .max(MIN_SLEEP),
+            None => poll_interval,
+        };
+        tokio::select! {
+            _ = wakeup.wait(timeout) => {},
+            _ = shutdown.requested() => {},
         }
+    }
+    info!("Runner stopped");
+    Ok(())
+}
+
+/// One pass over everything that might need doing.
+#[tracing::instrument(skip_all)]
+async fn tick(
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    clock: &dyn Clock,
+    scheduler: &Scheduler,
+    guild_cache: &GuildCache,
+    shutdown: &Shutdown,
+    last_purge: &mut Option<Instant>,
+) -> crate::error::Result {
+    let transaction = sentry::start_transaction(TransactionContext::new(
+        "runner",
+        "main-task",
+    ));
 
-        let span = transaction.start_child("db", "Fetch Events");
-        span.set_data("status", Value::String(EventStatus::Allowed.into()));
-        let allowed_events =
-            fetch_events_by_status(db_conn, EventStatus::Allowed).await?;
+    let child = transaction.start_child("db", "Fetch Events");
+    child.set_data("status", Value::String(EventStatus::NotAllowed.into()));
+    let not_allowed_events =
+        storage.fetch_events_by_status(EventStatus::NotAllowed).await?;
+    let status: String = EventStatus::NotAllowed.into();
+    metrics::EVENTS
+        .with_label_values(&[status.as_str()])
+        .set(not_allowed_events.len() as i64);
+
+    child.set_status(SpanStatus::Ok);
+    child.finish();
+
+    for event in not_allowed_events.into_iter() {
+        let span = transaction.start_child("db", "Has Allow Requests");
+        if storage.fetch_allow_request(event.id).await?.is_none() {
+            let ca_span = span.start_child("db", "Create Allow Request");
+            ca_span.set_data(
+                "allow-request",
+                serde_json::to_value(&event).unwrap(),
+            );
+            create_allow_request(storage, gateway, &event).await?;
+            ca_span.set_status(SpanStatus::Ok);
+            ca_span.finish();
+        }
         span.set_status(SpanStatus::Ok);
         span.finish();
+    }
 
-        struct QueuedGuild {
-            guild_id: String,
-            channel_to_post: ChannelId,
-            role: Option<String>,
-            event_id: i64,
-        }
+    let span = transaction.start_child("db", "Fetch Events");
+    span.set_data("status", Value::String(EventStatus::Allowed.into()));
+    let allowed_events =
+        storage.fetch_events_by_status(EventStatus::Allowed).await?;
+    let status: String = EventStatus::Allowed.into();
+    metrics::EVENTS
+        .with_label_values(&[status.as_str()])
+        .set(allowed_events.len() as i64);
+    span.set_status(SpanStatus::Ok);
+    span.finish();
+
+    struct QueuedGuild {
+        guild_id: i64,
+        channel_to_post: String,
+        role: Option<String>,
+        categories: Option<Vec<DocumentCategory>>,
+        event_id: i64,
+    }
 
-        let mt_queued_guilds = Arc::new(Mutex::new(Vec::new()));
+    let mt_queued_guilds = Arc::new(Mutex::new(Vec::new()));
+    let mut ready_documents = vec![];
 
-        for event in allowed_events.into_iter() {
-            let span = transaction.start_child("main-task", "Handle Event");
-            span.set_data("event", serde_json::to_value(&event).unwrap());
-            if (Utc::now() - event.created_at).num_days() > 10 {
-                mark_event_done(db_conn, event.id as i64).await?;
-            }
-            let gspan = &span;
-            let (ids, guilds, series): (Vec<_>, Vec<_>, Vec<_>) =
-                tokio::task::unconstrained(fetch_guilds(db_conn))
-                    .await?
-                    .into_iter()
-                    .map(|f| (f.id, f, event.series))
-                    .collect();
-            for chunk in guilds.chunks(30) {
-                let guild_tasks: Vec<_> = chunk
-                    .iter()
-                    .map(async |guild| -> crate::error::Result {
-                        tokio::task::yield_now().await;
-                        let (role, channel, use_threads) =
-                            guild.settings_for_series(event.series);
-                        let Some(channel) = channel else {
-                            return Ok(());
-                        };
-                        let nspan = gspan.start_child("guild", "Enqueue Guild");
-                        nspan.set_data(
-                            "guild",
-                            serde_json::to_value(guild).unwrap(),
-                        );
-                        let channel_to_post = if !use_threads {
-                            channel.to_owned()
-                        } else {
-                            match fetch_thread_for_guild_and_event(
-                                db_conn,
-                                guild.id,
-                                event.id as i64,
-                            )
-                            .await?
-                            {
-                                Some(c) => c.discord_id,
-                                None => {
-                                    create_new_thread(
-                                        db_conn, ctx, guild, &event,
-                                    )
-                                    .await?
-                                    .discord_id
-                                },
-                            }
-                        };
-                        {
-                            let mut queued_guilds =
-                                mt_queued_guilds.lock().await;
-                            queued_guilds.push(QueuedGuild {
-                                guild_id: guild.discord_id.to_owned(),
-                                event_id: event.id as i64,
-                                channel_to_post: ChannelId::new(
-                                    channel_to_post.parse()?,
-                                ),
-                                role: role.cloned(),
-                            });
-                        }
-                        nspan.finish();
-                        Ok(())
-                    })
-                    .collect();
-                let res = join_all(guild_tasks).await;
-                for ((res, _gid), series) in
-                    res.chunks(30).zip(ids.chunks(30)).zip(series.chunks(30))
+    for event in allowed_events.into_iter() {
+        if shutdown.is_requested() {
+            break;
+        }
+        let span = transaction.start_child("main-task", "Handle Event");
+        span.set_data("event", serde_json::to_value(&event).unwrap());
+        if (clock.now() - event.created_at).num_days() > 10 {
+            storage.mark_event_done(event.id as i64).await?;
+        }
+        let gspan = &span;
+        let disabled = storage.fetch_disabled_guild_ids(event.series).await?;
+        let subscribed = guild_cache.for_series(storage, event.series).await?;
+        let guilds: Vec<_> =
+            subscribed.iter().filter(|f| !disabled.contains(&f.id)).collect();
+        let guild_tasks: Vec<_> = guilds
+            .iter()
+            .map(async |guild| -> crate::error::Result {
+                tokio::task::yield_now().await;
+                let (role, _, _) = guild.settings_for_series(event.series);
+                let nspan = gspan.start_child("guild", "Enqueue Guild");
+                nspan.set_data("guild", serde_json::to_value(guild).unwrap());
+                let Some(channel_to_post) =
+                    destination(storage, gateway, scheduler, guild, &event)
+                        .await?
+                else {
+                    return Ok(());
+                };
                 {
-                    for ((result, _guild_id), _series) in
-                        res.iter().zip(_gid).zip(series)
+                    let mut queued_guilds = mt_queued_guilds.lock().await;
+                    queued_guilds.push(QueuedGuild {
+                        guild_id: guild.id,
+                        event_id: event.id as i64,
+                        channel_to_post,
+                        role: role.cloned(),
+                        categories: guild
+                            .categories_for_series(event.series)
+                            .map(<[_]>::to_vec),
+                    });
+                }
+                nspan.finish();
+                Ok(())
+            })
+            .collect();
+        let results = join_all(guild_tasks).await;
+        // A guild without a destination has no delivery yet, the documents
+        // of the event stay open until the next tick resolves it or its
+        // health disables it.
+        let mut unresolved = false;
+        for (result, guild) in results.into_iter().zip(&guilds) {
+            let Err(why) = result else {
+                continue;
+            };
+            unresolved = true;
+            error!(guild_id = guild.id, "{why}");
+            match why.class() {
+                ErrorClass::User => {
+                    if let Err(why) = health::record_failure(
+                        storage,
+                        gateway,
+                        guild,
+                        event.series,
+                        &why.user_message(),
+                    )
+                    .await
                     {
-                        if let Err(why) = result {
-                            match why {
-                            crate::error::Error::Serenity(e) => match e {
-                                serenity::Error::Model(serenity::all::ModelError::InvalidPermissions { .. }) => {
-                                        // if let Err(why) = database::clear_guild_settings(
-                                        //     db_conn,
-                                        //     guild_id.to_owned(),
-                                        //     series.to_owned()).await
-                                        // {
-                                        //     sentry::capture_error(&why);
-                                        // }
-                                }
-                                serenity::Error::Http(serenity::all::HttpError::UnsuccessfulRequest(_e)) => {
-                                    // match e.error.code {
-                                    //     10003 | 50013 => {
-                                    //     if let Err(why) = database::clear_guild_settings(
-                                    //         db_conn,
-                                    //         guild_id.to_owned(),
-                                    //         series.to_owned()).await
-                                    //     {
-                                    //         sentry::capture_error(&why);
-                                    //     }
-                                    //         },
-                                    //     _ => {}
-                                    // }
-                                }
-                                _ => {}
-                            },
-                            e => {
-                                sentry::capture_error(&e);
-                            },
-                        }
-                        }
+                        sentry::capture_error(&why);
                     }
-                }
+                },
+                ErrorClass::Internal => {
+                    sentry::capture_error(&why);
+                },
+                ErrorClass::Transient => {},
             }
-            for document in
-                fetch_docs_for_event(db_conn, event.id as i64).await?
-            {
-                let dspan = span.start_child("main-task", "Handle Document");
-                dspan.set_data(
-                    "document",
-                    serde_json::to_value(&document).unwrap(),
-                );
-                mark_doc_done(db_conn, document.id).await?;
-                let images =
-                    fetch_images_for_document(db_conn, document.id).await?;
-                let message_to_send = create_message(&document, images);
-                let queued_guilds = mt_queued_guilds.lock().await;
-                for chunk in queued_guilds.chunks(30) {
-                    let queued: Vec<_> = chunk
-                        .iter()
-                        .filter(|f| f.event_id == document.event_id)
-                        .map(async |queued| {
-                            let hub = sentry::Hub::new_from_top(
-                                sentry::Hub::current(),
-                            );
-                            let _guard = hub.push_scope();
-                            hub.configure_scope(|scope| {
-                                scope.set_user(Some(User {
-                                    id: Some(queued.guild_id.to_string()),
-                                    ..Default::default()
-                                }))
-                            });
-
-                            let mut msg = message_to_send.clone();
-                            if let Some(role) = &queued.role {
-                                msg = msg.content(format!("<@&{role}>"));
-                            }
-                            let channel_id = queued.channel_to_post;
-                            if let Err(why) =
-                                channel_id.send_message(ctx, msg).await
-                            {
-                                hub.capture_error(&why);
-                                error!(
-                                    guild_id = queued.guild_id.clone(),
-                                    document_id = document.id,
-                                    document_title = document.title.clone(),
-                                    "{why}"
-                                );
-                            }
-                        })
-                        .collect();
-                    drop(join_all(queued).await);
-                }
-                dspan.set_status(SpanStatus::Ok);
-                dspan.finish();
+        }
+        for document in storage.fetch_docs_for_event(event.id as i64).await? {
+            let dspan = span.start_child("main-task", "Enqueue Document");
+            dspan
+                .set_data("document", serde_json::to_value(&document).unwrap());
+            let category = document_category(storage, &document).await?;
+            let queued_guilds = mt_queued_guilds.lock().await;
+            for queued in queued_guilds.iter().filter(|f| {
+                f.event_id == document.event_id
+                    && f.categories
+                        .as_ref()
+                        .is_none_or(|f| f.contains(&category))
+            }) {
+                storage
+                    .insert_pending_delivery(
+                        document.id,
+                        queued.guild_id,
+                        &queued.channel_to_post,
+                        queued.role.as_deref(),
+                    )
+                    .await?;
+            }
+            if !unresolved {
+                ready_documents.push(document.id);
+            }
+            dspan.set_status(SpanStatus::Ok);
+            dspan.finish();
+        }
+        span.set_status(SpanStatus::Ok);
+        span.finish();
+    }
+
+    let span = transaction.start_child("main-task", "Dispatch Deliveries");
+    dispatch_due_deliveries(storage, gateway, clock, scheduler, shutdown)
+        .await?;
+    span.set_status(SpanStatus::Ok);
+    span.finish();
+
+    // Only done once every eligible guild got a terminal state, otherwise
+    // the next tick picks the leftovers up again.
+    for document_id in ready_documents {
+        if !storage.has_open_deliveries(document_id).await? {
+            storage.mark_doc_done(document_id).await?;
+            metrics::DOCUMENTS_POSTED.inc();
+        }
+    }
+
+    if last_purge.is_none_or(|f| f.elapsed() >= PURGE_INTERVAL) {
+        let span = transaction.start_child("db", "Purge Departed Guilds");
+        let cutoff = clock.now()
+            - TimeDelta::days(config::get().runner.guild_retention_days);
+        let purged = storage.purge_departed_guilds(cutoff).await?;
+        if purged > 0 {
+            info!(purged, "Purged guilds that removed the bot");
+        }
+        *last_purge = Some(Instant::now());
+        span.set_status(SpanStatus::Ok);
+        span.finish();
+    }
+
+    transaction.set_status(SpanStatus::Ok);
+    transaction.finish();
+    mt_queued_guilds.lock().await.clear();
+    Ok(())
+}
+
+/// The category stored with the document, classified from the title and
+/// stored the first time the document comes up.
+async fn document_category(
+    storage: &dyn Storage,
+    document: &Document,
+) -> crate::error::Result<DocumentCategory> {
+    if let Some(category) = storage.fetch_document_category(document.id).await?
+    {
+        return Ok(category);
+    }
+    let category = DocumentCategory::classify(&document.title);
+    storage.set_document_category(document.id, category).await?;
+    Ok(category)
+}
+
+/// Sends every pending delivery that is due, regardless of whether its
+/// document is still waiting to be posted. That way retries and replayed
+/// dead letters go out even after the event itself was marked done.
+///
+/// Deliveries are grouped by channel and each channel is worked through in
+/// order by a single task, while the scheduler bounds how many requests are
+/// in flight across all channels.
+#[tracing::instrument(skip_all)]
+async fn dispatch_due_deliveries(
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    clock: &dyn Clock,
+    scheduler: &Scheduler,
+    shutdown: &Shutdown,
+) -> crate::error::Result {
+    let due = storage.fetch_due_deliveries(clock.now()).await?;
+    if due.is_empty() {
+        return Ok(());
+    }
+    let started = Instant::now();
+    scheduler.enqueue(due.len());
+
+    let mut documents = HashMap::new();
+    for delivery in &due {
+        if documents.contains_key(&delivery.document_id) {
+            continue;
+        }
+        let Some(document) =
+            storage.fetch_document_by_id(delivery.document_id).await?
+        else {
+            continue;
+        };
+        let series = storage
+            .get_event_by_id(document.event_id as u64)
+            .await?
+            .map(|f| f.series);
+        let category = document_category(storage, &document).await?;
+        let images = storage.fetch_images_for_document(document.id).await?;
+        let message = create_message(&document, category, images);
+        documents.insert(document.id, (document, message, series));
+    }
+
+    let mut channels: HashMap<&str, Vec<&Delivery>> = HashMap::new();
+    for delivery in &due {
+        channels
+            .entry(delivery.channel_id.as_str())
+            .or_default()
+            .push(delivery);
+    }
+
+    let documents = &documents;
+    let channel_tasks = channels.into_values().map(async |deliveries| {
+        let mut deliveries = deliveries.into_iter();
+        for delivery in deliveries.by_ref() {
+            // Whatever is left stays pending for the next start.
+            if shutdown.is_requested() {
+                scheduler.dequeue();
+                break;
+            }
+            let Some((document, message, series)) =
+                documents.get(&delivery.document_id)
+            else {
+                scheduler.dequeue();
+                continue;
+            };
+            let result = deliver(
+                storage,
+                gateway,
+                clock,
+                scheduler,
+                delivery,
+                document,
+                message.clone(),
+                *series,
+            )
+            .await;
+            scheduler.dequeue();
+            match result {
+                Ok(true) => {},
+                // Keep the channel in order, the rest waits for the retry.
+                Ok(false) => break,
+                Err(why) => {
+                    sentry::capture_error(&why);
+                    error!("Error recording delivery: {why}");
+                    break;
+                },
             }
-            span.set_status(SpanStatus::Ok);
-            span.finish();
         }
-        transaction.set_status(SpanStatus::Ok);
-        transaction.finish();
-        mt_queued_guilds.lock().await.clear();
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        for _ in deliveries {
+            scheduler.dequeue();
+        }
+    });
+    join_all(channel_tasks).await;
+
+    let stats = scheduler.stats();
+    info!(
+        deliveries = due.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        queue_depth = stats.queue_depth,
+        sent = stats.sent,
+        failed = stats.failed,
+        throughput = stats.throughput,
+        "Dispatched deliveries"
+    );
+    Ok(())
+}
+
+/// Sends a single delivery and records the outcome. Returns whether later
+/// deliveries to the same channel may go out, which isn't the case while
+/// this one waits for a retry.
+#[tracing::instrument(
+    skip_all,
+    fields(delivery_id = delivery.id, document_id = delivery.document_id)
+)]
+async fn deliver(
+    storage: &dyn Storage,
+    gateway: &dyn Gateway,
+    clock: &dyn Clock,
+    scheduler: &Scheduler,
+    delivery: &Delivery,
+    document: &Document,
+    message: CreateMessage,
+    series: Option<Series>,
+) -> crate::error::Result<bool> {
+    let hub = sentry::Hub::new_from_top(sentry::Hub::current());
+    let _guard = hub.push_scope();
+    hub.configure_scope(|scope| {
+        scope.set_user(Some(User {
+            id: Some(delivery.guild_id.to_string()),
+            ..Default::default()
+        }))
+    });
+
+    let message = with_role_mention(message, delivery.role_id.as_deref());
+    let Some(channel_id) = parse_channel_id(&delivery.channel_id) else {
+        let error = format!("invalid channel id {:?}", delivery.channel_id);
+        metrics::DELIVERIES
+            .with_label_values(&["failed", ErrorClass::Internal.as_str()])
+            .inc();
+        storage.mark_delivery_failed(delivery.id, &error).await?;
+        error!(
+            guild_id = delivery.guild_id,
+            document_id = document.id,
+            "{error}"
+        );
+        return Ok(true);
+    };
+    let result = {
+        let _permit = scheduler.acquire(gateway, Some(channel_id)).await;
+        gateway.send_message(channel_id, message).await
+    };
+    let why = match result {
+        Ok(message_id) => {
+            scheduler.record_sent();
+            metrics::DELIVERIES.with_label_values(&["sent", "none"]).inc();
+            storage
+                .mark_delivery_sent(delivery.id, &message_id.to_string())
+                .await?;
+            if let Some(series) = series {
+                storage
+                    .reset_destination_health(delivery.guild_id, series)
+                    .await?;
+            }
+            return Ok(true);
+        },
+        Err(why) => why,
+    };
+
+    scheduler.record_failed();
+    let attempts = delivery.attempts + 1;
+    let error = why.to_string();
+    let error_class = why.class().as_str();
+    match retry::classify_error(&why) {
+        FailureKind::Transient
+            if attempts < config::get().runner.max_attempts =>
+        {
+            metrics::DELIVERIES
+                .with_label_values(&["retry", error_class])
+                .inc();
+            warn!(
+                guild_id = delivery.guild_id,
+                document_id = document.id,
+                attempts,
+                "Retrying delivery: {why}"
+            );
+            storage
+                .schedule_delivery_retry(
+                    delivery.id,
+                    &error,
+                    retry::next_attempt_at(clock.now(), attempts),
+                )
+                .await?;
+            return Ok(false);
+        },
+        FailureKind::Transient => {
+            metrics::DELIVERIES
+                .with_label_values(&["dead_letter", error_class])
+                .inc();
+            storage.mark_delivery_dead(delivery.id, &error).await?
+        },
+        FailureKind::Permanent => {
+            metrics::DELIVERIES
+                .with_label_values(&["failed", error_class])
+                .inc();
+            storage.mark_delivery_failed(delivery.id, &error).await?;
+            // Only count what the guild can fix against its destination.
+            if let (ErrorClass::User, Some(series), Some(guild)) = (
+                why.class(),
+                series,
+                storage.fetch_guild_by_id(delivery.guild_id).await?,
+            ) {
+                health::record_failure(
+                    storage,
+                    gateway,
+                    &guild,
+                    series,
+                    &why.user_message(),
+                )
+                .await?;
+            }
+        },
+    }
+    if why.is_reportable() {
+        hub.capture_error(&why);
     }
+    error!(
+        guild_id = delivery.guild_id,
+        document_id = document.id,
+        document_title = document.title.clone(),
+        attempts,
+        error_class,
+        "{why}"
+    );
+    Ok(true)
 }
@@ -0,0 +1,38 @@
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{
+    config,
+    error::{Error, ErrorClass},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// Worth trying again later: 5xx, rate limits, timeouts and the like.
+    Transient,
+    /// Retrying won't help: the channel is gone or access was revoked.
+    Permanent,
+}
+
+/// Whether whatever a gateway call returned is worth another try, see
+/// [`Error::class`].
+pub fn classify_error(error: &Error) -> FailureKind {
+    match error.class() {
+        ErrorClass::Transient => FailureKind::Transient,
+        ErrorClass::User | ErrorClass::Internal => FailureKind::Permanent,
+    }
+}
+
+/// Point in time at which a delivery that already failed `attempts` times
+/// should be tried again.
+pub fn next_attempt_at(
+    now: DateTime<Utc>,
+    attempts: i64,
+) -> DateTime<Utc> {
+    let runner = &config::get().runner;
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    let delay = runner
+        .retry_base_delay_secs
+        .saturating_mul(2_i64.saturating_pow(exponent))
+        .min(runner.retry_max_delay_secs);
+    now + TimeDelta::seconds(delay)
+}
@@ -0,0 +1,121 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serenity::{all::ChannelId, prelude::TypeMapKey};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::gateway::Gateway;
+
+/// Hands out permission to talk to Discord.
+///
+/// Limits the requests in flight across all guilds, holds everything back
+/// while a global rate limit is active and waits for exhausted channel
+/// buckets to reset before a request would just sit in serenity's
+/// ratelimiter while blocking a slot. Ordering per channel is up to the
+/// caller: send to a channel from a single task, one message at a time.
+#[derive(Debug)]
+pub struct Scheduler {
+    permits: Semaphore,
+    paused_until: Mutex<Option<Instant>>,
+    queued: AtomicUsize,
+    sent: AtomicU64,
+    failed: AtomicU64,
+    started: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SchedulerStats {
+    /// Deliveries waiting to be sent in the current dispatch.
+    pub queue_depth: usize,
+    pub sent: u64,
+    pub failed: u64,
+    /// Average messages sent per second since startup.
+    pub throughput: f64,
+}
+
+impl TypeMapKey for Scheduler {
+    type Value = Arc<Scheduler>;
+}
+
+impl Scheduler {
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            permits: Semaphore::new(concurrency.max(1)),
+            paused_until: Mutex::new(None),
+            queued: AtomicUsize::new(0),
+            sent: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    /// Holds back every request for `duration`, used when Discord reports a
+    /// global rate limit.
+    pub fn pause(
+        &self,
+        duration: Duration,
+    ) {
+        let until = Instant::now() + duration;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_none_or(|f| f < until) {
+            *paused_until = Some(until);
+        }
+    }
+
+    /// Waits until a request to the channel can go out right away.
+    pub async fn acquire(
+        &self,
+        gateway: &dyn Gateway,
+        channel_id: Option<ChannelId>,
+    ) -> SemaphorePermit<'_> {
+        let paused_until = *self.paused_until.lock().unwrap();
+        if let Some(until) = paused_until {
+            tokio::time::sleep_until(until.into()).await;
+        }
+        if let Some(channel_id) = channel_id {
+            if let Some(wait) = gateway.channel_backoff(channel_id).await {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        self.permits.acquire().await.expect("scheduler semaphore closed")
+    }
+
+    pub fn enqueue(
+        &self,
+        amount: usize,
+    ) {
+        self.queued.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn dequeue(&self) {
+        _ = self.queued.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |f| Some(f.saturating_sub(1)),
+        );
+    }
+
+    pub fn record_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> SchedulerStats {
+        let sent = self.sent.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed().as_secs_f64().max(1.0);
+        SchedulerStats {
+            queue_depth: self.queued.load(Ordering::Relaxed),
+            sent,
+            failed: self.failed.load(Ordering::Relaxed),
+            throughput: sent as f64 / elapsed,
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::prelude::TypeMapKey;
+use tokio::sync::watch;
+
+/// Coordinates stopping the bot without cutting the runner off in the
+/// middle of a fan-out.
+///
+/// Once requested the runner stops picking up new work and finishes the
+/// deliveries already in flight. Anything it didn't get to stays `Pending`
+/// and goes out after the next start.
+#[derive(Debug)]
+pub struct Shutdown {
+    requested: watch::Sender<bool>,
+    drained: watch::Sender<bool>,
+}
+
+impl TypeMapKey for Shutdown {
+    type Value = Arc<Shutdown>;
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            requested: watch::Sender::new(false),
+            // Nothing to drain until the runner started.
+            drained: watch::Sender::new(true),
+        }
+    }
+}
+
+/// Marks the runner as drained when dropped, however it stopped.
+pub struct RunnerGuard<'a>(&'a Shutdown);
+
+impl Drop for RunnerGuard<'_> {
+    fn drop(&mut self) {
+        self.0.drained.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    pub fn request(&self) {
+        self.requested.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Resolves once a shutdown got requested.
+    pub async fn requested(&self) {
+        let mut receiver = self.requested.subscribe();
+        _ = receiver.wait_for(|requested| *requested).await;
+    }
+
+    /// Keeps the shutdown waiting for the runner until the guard is dropped.
+    pub fn track_runner(&self) -> RunnerGuard<'_> {
+        self.drained.send_replace(false);
+        RunnerGuard(self)
+    }
+
+    /// Waits up to `deadline` for the runner to stop, returns whether it did.
+    pub async fn drained(
+        &self,
+        deadline: Duration,
+    ) -> bool {
+        let mut receiver = self.drained.subscribe();
+        tokio::time::timeout(deadline, receiver.wait_for(|drained| *drained))
+            .await
+            .is_ok()
+    }
+}
@@ -0,0 +1,531 @@
+//! Scenarios run against an in-memory database and a gateway that records
+//! what would have been sent to Discord, one runner tick at a time.
+
+use std::time::Instant;
+
+use chrono::{Datelike, TimeDelta, Utc};
+use f1_bot_types::{Document, DocumentStatus, Event, EventStatus, Series};
+use serde_json::{Value, json};
+use serenity::all::{ChannelId, UserId};
+
+use super::{
+    clock::{Clock, ManualClock},
+    guild_cache::GuildCache,
+    scheduler::Scheduler,
+    shutdown::Shutdown,
+    tick,
+    wakeup::Wakeup,
+};
+use crate::{
+    config,
+    database::timestamp,
+    event_manager::update_allow_request,
+    gateway::recording::{Failure, Outbound, RecordingGateway},
+    model::{
+        allow_request::AllowRequestStatus,
+        category::DocumentCategory,
+        delivery::{Delivery, DeliveryStatus},
+    },
+    storage::{Storage, memory::MemoryStorage},
+};
+
+/// The bot with nothing but a database and a fake Discord.
+struct Harness {
+    storage: MemoryStorage,
+    gateway: RecordingGateway,
+    clock: ManualClock,
+    scheduler: Scheduler,
+    guild_cache: GuildCache,
+    shutdown: Shutdown,
+    wakeup: Wakeup,
+    last_purge: Option<Instant>,
+    last_id: u64,
+}
+
+impl Harness {
+    fn new() -> Self {
+        config::init_for_tests();
+        Self {
+            storage: MemoryStorage::default(),
+            gateway: RecordingGateway::default(),
+            clock: ManualClock::new(Utc::now()),
+            scheduler: Scheduler::new(4),
+            guild_cache: GuildCache::default(),
+            shutdown: Shutdown::default(),
+            wakeup: Wakeup::default(),
+            last_purge: None,
+            last_id: 0,
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Adds an event created right now, returns its id.
+    fn event(
+        &mut self,
+        series: Series,
+        status: EventStatus,
+    ) -> u64 {
+        let id = self.next_id();
+        let event: Event = serde_json::from_value(json!({
+            "id": id,
+            "series": series,
+            // Only events of the running season are picked up.
+            "year": Utc::now().year(),
+            "title": format!("Grand Prix {id}"),
+            "created_at": timestamp(self.clock.now()),
+            "status": status,
+        }))
+        .unwrap();
+        self.storage.insert_event(&event).unwrap();
+        id
+    }
+
+    /// Adds a document with a single page that is ready to be posted,
+    /// returns its id.
+    fn document(
+        &mut self,
+        event_id: u64,
+        title: &str,
+    ) -> i64 {
+        let id = self.next_id() as i64;
+        let document: Document = serde_json::from_value(json!({
+            "id": id,
+            "event_id": event_id,
+            "title": title,
+            "href": format!("https://www.fia.com/documents/{id}.pdf"),
+            "mirror": format!("https://mirror.example/{id}.pdf"),
+            "created_at": timestamp(self.clock.now()),
+            "status": DocumentStatus::ReadyToPost,
+        }))
+        .unwrap();
+        self.storage.insert_document(&document).unwrap();
+        self.storage.insert_image(
+            id,
+            &format!("https://mirror.example/{id}-1.png"),
+            1,
+        );
+        id
+    }
+
+    /// Adds a guild that gets `series` in `channel`, returns its id.
+    async fn guild(
+        &self,
+        discord_id: u64,
+        series: Series,
+        channel: u64,
+        role: Option<u64>,
+        threads: bool,
+    ) -> i64 {
+        let discord_id = discord_id.to_string();
+        let storage = &self.storage;
+        storage
+            .upsert_guild(
+                &discord_id,
+                &format!("Guild {discord_id}"),
+                self.clock.now(),
+            )
+            .await
+            .unwrap();
+        storage
+            .upsert_series_settings(
+                &discord_id,
+                series,
+                Some(&channel.to_string()),
+                role.map(|f| f.to_string()).as_deref(),
+                threads,
+            )
+            .await
+            .unwrap();
+        self.guild_cache.invalidate().await;
+        storage
+            .fetch_guild_by_discord_id(&discord_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .id
+    }
+
+    async fn tick(&mut self) {
+        tick(
+            &self.storage,
+            &self.gateway,
+            &self.clock,
+            &self.scheduler,
+            &self.guild_cache,
+            &self.shutdown,
+            &mut self.last_purge,
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Allows the event the way the button in the request channel does.
+    async fn approve(
+        &self,
+        event_id: u64,
+    ) {
+        let request = self
+            .storage
+            .fetch_allow_request(event_id)
+            .await
+            .unwrap()
+            .expect("no allow request for the event");
+        update_allow_request(
+            &self.storage,
+            &self.wakeup,
+            request.id,
+            UserId::new(1),
+            AllowRequestStatus::Allowed,
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn event_status(
+        &self,
+        event_id: u64,
+    ) -> String {
+        let event = self.storage.get_event_by_id(event_id).await.unwrap();
+        event.expect("no such event").status.into()
+    }
+
+    fn posted_in(
+        &self,
+        channel: u64,
+    ) -> Vec<Value> {
+        self.gateway.messages_in(ChannelId::new(channel))
+    }
+
+    /// The only thread opened in the channel.
+    fn thread_in(
+        &self,
+        channel: u64,
+    ) -> u64 {
+        let threads = self.gateway.threads_in(ChannelId::new(channel));
+        assert_eq!(threads.len(), 1, "threads in {channel}: {threads:?}");
+        threads[0].get()
+    }
+
+    fn deliveries_for(
+        &self,
+        guild_id: i64,
+    ) -> Vec<Delivery> {
+        let deliveries = self.storage.deliveries().unwrap();
+        deliveries.into_iter().filter(|f| f.guild_id == guild_id).collect()
+    }
+}
+
+/// Titles of the documents in the messages, in order.
+fn titles(messages: &[Value]) -> Vec<&str> {
+    messages
+        .iter()
+        .map(|f| f["embeds"][0]["title"].as_str().unwrap_or_default())
+        .collect()
+}
+
+fn is(
+    delivery: &Delivery,
+    status: DeliveryStatus,
+) -> bool {
+    delivery.status.to_str() == status.to_str()
+}
+
+#[tokio::test]
+async fn new_event_is_sent_for_approval_once() {
+    let mut h = Harness::new();
+    h.guild(10, Series::F1, 100, None, false).await;
+    let event = h.event(Series::F1, EventStatus::NotAllowed);
+    h.document(event, "Entry List");
+
+    h.tick().await;
+    h.tick().await;
+
+    let requests: Vec<_> = h
+        .gateway
+        .outbound()
+        .into_iter()
+        .filter_map(|f| match f {
+            Outbound::AllowRequest {
+                event_id,
+                ..
+            } => Some(event_id),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(requests, [event]);
+    assert_eq!(h.storage.allow_requests().unwrap().len(), 1);
+    assert!(h.posted_in(100).is_empty());
+}
+
+#[tokio::test]
+async fn approved_event_gets_a_thread_with_its_documents() {
+    let mut h = Harness::new();
+    h.guild(10, Series::F1, 100, None, true).await;
+    let event = h.event(Series::F1, EventStatus::NotAllowed);
+    h.document(event, "Entry List");
+
+    h.tick().await;
+    assert!(h.gateway.threads_in(ChannelId::new(100)).is_empty());
+
+    h.approve(event).await;
+    assert_eq!(h.event_status(event).await, String::from(EventStatus::Allowed));
+    h.tick().await;
+
+    let thread = h.thread_in(100);
+    assert_eq!(titles(&h.posted_in(thread)), ["Entry List"]);
+    assert!(h.posted_in(100).is_empty());
+
+    // Posted documents don't go out again.
+    h.tick().await;
+    assert_eq!(h.posted_in(thread).len(), 1);
+}
+
+#[tokio::test]
+async fn later_documents_reuse_the_thread() {
+    let mut h = Harness::new();
+    h.guild(10, Series::F2, 100, None, true).await;
+    let event = h.event(Series::F2, EventStatus::Allowed);
+    h.document(event, "Summons");
+
+    h.tick().await;
+    h.clock.advance(TimeDelta::hours(2));
+    h.document(event, "Decision");
+    h.tick().await;
+
+    let thread = h.thread_in(100);
+    assert_eq!(titles(&h.posted_in(thread)), ["Summons", "Decision"]);
+    assert_eq!(h.storage.threads().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn notify_role_is_mentioned() {
+    let mut h = Harness::new();
+    h.guild(10, Series::F1, 100, Some(500), false).await;
+    h.guild(11, Series::F1, 101, None, false).await;
+    let event = h.event(Series::F1, EventStatus::Allowed);
+    h.document(event, "Starting Grid");
+
+    h.tick().await;
+
+    let with_role = h.posted_in(100);
+    assert_eq!(titles(&with_role), ["Starting Grid"]);
+    assert_eq!(with_role[0]["content"], "<@&500>");
+    let without_role = h.posted_in(101);
+    assert_eq!(titles(&without_role), ["Starting Grid"]);
+    assert!(without_role[0]["content"].is_null());
+}
+
+#[tokio::test]
+async fn events_expire_after_ten_days() {
+    let mut h = Harness::new();
+    h.guild(10, Series::F3, 100, None, false).await;
+    let event = h.event(Series::F3, EventStatus::Allowed);
+    h.document(event, "Classification");
+
+    h.tick().await;
+    h.clock.advance(TimeDelta::days(10));
+    h.tick().await;
+    assert_eq!(h.event_status(event).await, String::from(EventStatus::Allowed));
+
+    h.clock.advance(TimeDelta::days(1));
+    h.tick().await;
+    assert_eq!(h.event_status(event).await, String::from(EventStatus::Posted));
+
+    h.document(event, "Late Decision");
+    h.tick().await;
+    assert_eq!(titles(&h.posted_in(100)), ["Classification"]);
+}
+
+#[tokio::test]
+async fn broken_guild_does_not_hold_back_the_others() {
+    let mut h = Harness::new();
+    let broken = h.guild(10, Series::F1, 100, None, false).await;
+    h.guild(11, Series::F1, 101, None, false).await;
+    h.gateway.fail(ChannelId::new(100), Failure::Permanent);
+    let event = h.event(Series::F1, EventStatus::Allowed);
+    h.document(event, "Entry List");
+
+    h.tick().await;
+
+    assert_eq!(titles(&h.posted_in(101)), ["Entry List"]);
+    let deliveries = h.deliveries_for(broken);
+    assert_eq!(deliveries.len(), 1);
+    assert!(is(&deliveries[0], DeliveryStatus::Failed));
+    let health = h
+        .storage
+        .fetch_destination_health(broken, Series::F1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(health.consecutive_failures, 1);
+
+    // Every guild got a final answer, so the document is done.
+    h.tick().await;
+    assert_eq!(h.posted_in(101).len(), 1);
+}
+
+#[tokio::test]
+async fn outage_is_retried_once_the_backoff_passed() {
+    let mut h = Harness::new();
+    let flaky = h.guild(10, Series::F1, 100, None, false).await;
+    h.guild(11, Series::F1, 101, None, false).await;
+    h.gateway.fail(ChannelId::new(100), Failure::Transient);
+    let event = h.event(Series::F1, EventStatus::Allowed);
+    h.document(event, "Entry List");
+
+    h.tick().await;
+    assert_eq!(titles(&h.posted_in(101)), ["Entry List"]);
+    let deliveries = h.deliveries_for(flaky);
+    assert!(is(&deliveries[0], DeliveryStatus::Pending));
+    assert!(deliveries[0].next_attempt_at.is_some());
+
+    h.gateway.recover(ChannelId::new(100));
+    h.tick().await;
+    assert!(h.posted_in(100).is_empty());
+
+    let delay = config::get().runner.retry_base_delay_secs;
+    h.clock.advance(TimeDelta::seconds(delay));
+    h.tick().await;
+    assert_eq!(titles(&h.posted_in(100)), ["Entry List"]);
+    assert!(is(&h.deliveries_for(flaky)[0], DeliveryStatus::Sent));
+    assert_eq!(h.posted_in(101).len(), 1);
+}
+
+#[tokio::test]
+async fn destination_is_disabled_after_repeated_failures() {
+    let mut h = Harness::new();
+    let broken = h.guild(10, Series::F1, 100, None, false).await;
+    h.guild(11, Series::F1, 101, None, false).await;
+    h.gateway.fail(ChannelId::new(100), Failure::Permanent);
+    let event = h.event(Series::F1, EventStatus::Allowed);
+    let failures = config::get().runner.disable_after_failures;
+    for n in 0..failures {
+        h.document(event, &format!("Decision {n}"));
+    }
+
+    h.tick().await;
+    let notices = h
+        .gateway
+        .outbound()
+        .into_iter()
+        .filter(|f| matches!(f, Outbound::GuildNotice { .. }))
+        .count();
+    assert_eq!(notices, 1);
+
+    h.document(event, "Final Classification");
+    h.tick().await;
+    assert_eq!(h.deliveries_for(broken).len() as i64, failures);
+    assert_eq!(h.posted_in(101).len() as i64, failures + 1);
+}
+
+#[tokio::test]
+async fn failed_thread_only_skips_that_guild() {
+    let mut h = Harness::new();
+    let broken = h.guild(10, Series::F1, 100, None, true).await;
+    h.guild(11, Series::F1, 101, None, true).await;
+    h.gateway.fail(ChannelId::new(100), Failure::Permanent);
+    let event = h.event(Series::F1, EventStatus::Allowed);
+    h.document(event, "Entry List");
+
+    h.tick().await;
+
+    assert!(h.gateway.threads_in(ChannelId::new(100)).is_empty());
+    assert!(h.deliveries_for(broken).is_empty());
+    let thread = h.thread_in(101);
+    assert_eq!(titles(&h.posted_in(thread)), ["Entry List"]);
+    let health = h
+        .storage
+        .fetch_destination_health(broken, Series::F1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(health.consecutive_failures, 1);
+}
+
+#[tokio::test]
+async fn document_waits_for_a_thread_that_failed_to_open() {
+    let mut h = Harness::new();
+    h.guild(10, Series::F1, 100, None, true).await;
+    h.guild(11, Series::F1, 101, None, true).await;
+    h.gateway.fail(ChannelId::new(100), Failure::Transient);
+    let event = h.event(Series::F1, EventStatus::Allowed);
+    h.document(event, "Entry List");
+
+    h.tick().await;
+    let thread = h.thread_in(101);
+    assert_eq!(titles(&h.posted_in(thread)), ["Entry List"]);
+    let ready = h.storage.fetch_docs_for_event(event as i64).await.unwrap();
+    assert_eq!(ready.len(), 1);
+
+    h.gateway.recover(ChannelId::new(100));
+    h.tick().await;
+    let late = h.thread_in(100);
+    assert_eq!(titles(&h.posted_in(late)), ["Entry List"]);
+    assert_eq!(h.posted_in(thread).len(), 1);
+    let ready = h.storage.fetch_docs_for_event(event as i64).await.unwrap();
+    assert!(ready.is_empty());
+}
+
+#[tokio::test]
+async fn delivery_to_a_bad_channel_id_fails_for_good() {
+    let mut h = Harness::new();
+    let guild = h.guild(10, Series::F1, 100, None, false).await;
+    let event = h.event(Series::F1, EventStatus::Allowed);
+    let document = h.document(event, "Entry List");
+    h.storage
+        .insert_pending_delivery(document, guild, "0", None)
+        .await
+        .unwrap();
+
+    h.tick().await;
+
+    assert_eq!(titles(&h.posted_in(100)), ["Entry List"]);
+    let deliveries = h.deliveries_for(guild);
+    let bad = deliveries.iter().find(|f| f.channel_id == "0").unwrap();
+    assert!(is(bad, DeliveryStatus::Failed));
+    assert!(
+        h.storage.fetch_docs_for_event(event as i64).await.unwrap().is_empty()
+    );
+}
+
+#[tokio::test]
+async fn guilds_only_get_the_categories_they_picked() {
+    let mut h = Harness::new();
+    h.guild(10, Series::F1, 100, None, false).await;
+    h.guild(11, Series::F1, 101, None, false).await;
+    let picked = [DocumentCategory::Decision, DocumentCategory::Summons];
+    h.storage
+        .update_series_categories("10", Series::F1, Some(&picked))
+        .await
+        .unwrap();
+    h.guild_cache.invalidate().await;
+    let event = h.event(Series::F1, EventStatus::Allowed);
+    let entry_list = h.document(event, "Entry List");
+    h.document(event, "Summons - Car 44 - Impeding");
+    h.document(event, "Offence - Car 1 - Unsafe release");
+
+    h.tick().await;
+
+    let filtered = h.posted_in(100);
+    assert_eq!(
+        titles(&filtered),
+        ["Summons - Car 44 - Impeding", "Offence - Car 1 - Unsafe release"]
+    );
+    assert_eq!(
+        filtered[0]["embeds"][0]["author"]["name"],
+        "FIA Document - Summons"
+    );
+    assert_eq!(
+        filtered[1]["embeds"][0]["color"],
+        DocumentCategory::Decision.color()
+    );
+    assert_eq!(h.posted_in(101).len(), 3);
+    assert_eq!(
+        h.storage.fetch_document_category(entry_list).await.unwrap(),
+        Some(DocumentCategory::EntryList)
+    );
+}
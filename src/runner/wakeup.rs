@@ -0,0 +1,32 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::prelude::TypeMapKey;
+use tokio::sync::Notify;
+
+/// Wakes the runner up whenever there might be new work, so it doesn't have
+/// to poll the database every few seconds.
+#[derive(Debug, Default)]
+pub struct Wakeup {
+    notify: Notify,
+}
+
+impl TypeMapKey for Wakeup {
+    type Value = Arc<Wakeup>;
+}
+
+impl Wakeup {
+    /// Signals the runner. If it is busy right now the signal is kept and the
+    /// next wait returns immediately.
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Sleeps until signalled or until `timeout` passed, returns whether the
+    /// runner was signalled.
+    pub async fn wait(
+        &self,
+        timeout: Duration,
+    ) -> bool {
+        tokio::time::timeout(timeout, self.notify.notified()).await.is_ok()
+    }
+}
@@ -0,0 +1,378 @@
+use ::libsql::Connection;
+use chrono::{DateTime, Utc};
+use f1_bot_types::{Document, Event, EventStatus, Image, Series};
+use serenity::async_trait;
+
+use super::Storage;
+use crate::{
+    database,
+    error::Result,
+    model::{
+        allow_request::{AllowRequest, AllowRequestStatus},
+        category::DocumentCategory,
+        delivery::Delivery,
+        guild::Guild,
+        health::DestinationHealth,
+        thread::Thread,
+    },
+};
+
+/// [`Storage`] backed by the libsql database, whether that's a replica, a
+/// local file or in memory.
+pub struct LibsqlStorage {
+    conn: Connection,
+}
+
+impl LibsqlStorage {
+    pub fn new(conn: Connection) -> Self {
+        Self {
+            conn,
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for LibsqlStorage {
+    async fn fetch_latest_event_by_series(
+        &self,
+        series: Series,
+    ) -> Result<Option<Event>> {
+        database::fetch_latest_event_by_series(&self.conn, series).await
+    }
+
+    async fn fetch_events_by_status(
+        &self,
+        status: EventStatus,
+    ) -> Result<Vec<Event>> {
+        database::fetch_events_by_status(&self.conn, status).await
+    }
+
+    async fn get_event_by_id(
+        &self,
+        id: u64,
+    ) -> Result<Option<Event>> {
+        database::get_event_by_id(&self.conn, id).await
+    }
+
+    async fn mark_event_done(
+        &self,
+        event_id: i64,
+    ) -> Result {
+        database::mark_event_done(&self.conn, event_id).await
+    }
+
+    async fn fetch_allow_request(
+        &self,
+        event_id: u64,
+    ) -> Result<Option<AllowRequest>> {
+        database::fetch_allow_request(&self.conn, event_id).await
+    }
+
+    async fn insert_allow_request(
+        &self,
+        event_id: u64,
+    ) -> Result<AllowRequest> {
+        database::insert_allow_request(&self.conn, event_id).await
+    }
+
+    async fn resolve_allow_request(
+        &self,
+        id: i64,
+        user_id: &str,
+        new_status: AllowRequestStatus,
+    ) -> Result {
+        database::resolve_allow_request(&self.conn, id, user_id, new_status)
+            .await
+    }
+
+    async fn fetch_guilds_for_series(
+        &self,
+        series: Series,
+    ) -> Result<Vec<Guild>> {
+        database::fetch_guilds_for_series(&self.conn, series).await
+    }
+
+    async fn fetch_guild_by_id(
+        &self,
+        guild_id: i64,
+    ) -> Result<Option<Guild>> {
+        database::fetch_guild_by_id(&self.conn, guild_id).await
+    }
+
+    async fn fetch_guild_by_discord_id(
+        &self,
+        discord_id: &str,
+    ) -> Result<Option<Guild>> {
+        database::fetch_guild_by_discord_id(&self.conn, discord_id).await
+    }
+
+    async fn upsert_guild(
+        &self,
+        discord_id: &str,
+        name: &str,
+        joined_at: DateTime<Utc>,
+    ) -> Result {
+        database::upsert_guild(&self.conn, discord_id, name, joined_at).await
+    }
+
+    async fn rename_guild(
+        &self,
+        discord_id: &str,
+        name: &str,
+    ) -> Result {
+        database::rename_guild(&self.conn, discord_id, name).await
+    }
+
+    async fn mark_guild_left(
+        &self,
+        discord_id: &str,
+    ) -> Result {
+        database::mark_guild_left(&self.conn, discord_id).await
+    }
+
+    async fn restore_guild(
+        &self,
+        discord_id: &str,
+    ) -> Result<bool> {
+        database::restore_guild(&self.conn, discord_id).await
+    }
+
+    async fn purge_departed_guilds(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64> {
+        database::purge_departed_guilds(&self.conn, cutoff).await
+    }
+
+    async fn upsert_series_settings(
+        &self,
+        discord_id: &str,
+        series: Series,
+        channel: Option<&str>,
+        role: Option<&str>,
+        threads: bool,
+    ) -> Result<u64> {
+        database::upsert_series_settings(
+            &self.conn, discord_id, series, channel, role, threads,
+        )
+        .await
+    }
+
+    async fn update_series_categories(
+        &self,
+        discord_id: &str,
+        series: Series,
+        categories: Option<&[DocumentCategory]>,
+    ) -> Result<u64> {
+        database::update_series_categories(
+            &self.conn, discord_id, series, categories,
+        )
+        .await
+    }
+
+    async fn fetch_thread_for_guild_and_event(
+        &self,
+        guild_id: i64,
+        event_id: i64,
+    ) -> Result<Option<Thread>> {
+        database::fetch_thread_for_guild_and_event(
+            &self.conn, guild_id, event_id,
+        )
+        .await
+    }
+
+    async fn insert_new_thread(
+        &self,
+        discord_id: &str,
+        guild_id: i64,
+        event_id: i64,
+        channel_id: &str,
+    ) -> Result<i64> {
+        database::insert_new_thread(
+            &self.conn, discord_id, guild_id, event_id, channel_id,
+        )
+        .await
+    }
+
+    async fn fetch_docs_for_event(
+        &self,
+        event_id: i64,
+    ) -> Result<Vec<Document>> {
+        database::fetch_docs_for_event(&self.conn, event_id).await
+    }
+
+    async fn fetch_document_by_id(
+        &self,
+        document_id: i64,
+    ) -> Result<Option<Document>> {
+        database::fetch_document_by_id(&self.conn, document_id).await
+    }
+
+    async fn fetch_document_category(
+        &self,
+        document_id: i64,
+    ) -> Result<Option<DocumentCategory>> {
+        database::fetch_document_category(&self.conn, document_id).await
+    }
+
+    async fn set_document_category(
+        &self,
+        document_id: i64,
+        category: DocumentCategory,
+    ) -> Result {
+        database::set_document_category(&self.conn, document_id, category).await
+    }
+
+    async fn mark_doc_done(
+        &self,
+        document_id: i64,
+    ) -> Result {
+        database::mark_doc_done(&self.conn, document_id).await
+    }
+
+    async fn fetch_images_for_document(
+        &self,
+        document_id: i64,
+    ) -> Result<Vec<Image>> {
+        database::fetch_images_for_document(&self.conn, document_id).await
+    }
+
+    async fn insert_pending_delivery(
+        &self,
+        document_id: i64,
+        guild_id: i64,
+        channel_id: &str,
+        role_id: Option<&str>,
+    ) -> Result {
+        database::insert_pending_delivery(
+            &self.conn,
+            document_id,
+            guild_id,
+            channel_id,
+            role_id,
+        )
+        .await
+    }
+
+    async fn fetch_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Delivery>> {
+        database::fetch_due_deliveries(&self.conn, now).await
+    }
+
+    async fn fetch_next_attempt_at(&self) -> Result<Option<DateTime<Utc>>> {
+        database::fetch_next_attempt_at(&self.conn).await
+    }
+
+    async fn fetch_last_sent_at(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<Option<DateTime<Utc>>> {
+        database::fetch_last_sent_at(&self.conn, guild_id, series).await
+    }
+
+    async fn mark_delivery_sent(
+        &self,
+        delivery_id: i64,
+        message_id: &str,
+    ) -> Result {
+        database::mark_delivery_sent(&self.conn, delivery_id, message_id).await
+    }
+
+    async fn mark_delivery_failed(
+        &self,
+        delivery_id: i64,
+        error: &str,
+    ) -> Result {
+        database::mark_delivery_failed(&self.conn, delivery_id, error).await
+    }
+
+    async fn mark_delivery_dead(
+        &self,
+        delivery_id: i64,
+        error: &str,
+    ) -> Result {
+        database::mark_delivery_dead(&self.conn, delivery_id, error).await
+    }
+
+    async fn schedule_delivery_retry(
+        &self,
+        delivery_id: i64,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result {
+        database::schedule_delivery_retry(
+            &self.conn,
+            delivery_id,
+            error,
+            next_attempt_at,
+        )
+        .await
+    }
+
+    async fn has_open_deliveries(
+        &self,
+        document_id: i64,
+    ) -> Result<bool> {
+        database::has_open_deliveries(&self.conn, document_id).await
+    }
+
+    async fn fetch_dead_letters(
+        &self,
+        limit: u32,
+    ) -> Result<Vec<Delivery>> {
+        database::fetch_dead_letters(&self.conn, limit).await
+    }
+
+    async fn replay_dead_letters(
+        &self,
+        delivery_id: Option<i64>,
+    ) -> Result<u64> {
+        database::replay_dead_letters(&self.conn, delivery_id).await
+    }
+
+    async fn fetch_destination_health(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<Option<DestinationHealth>> {
+        database::fetch_destination_health(&self.conn, guild_id, series).await
+    }
+
+    async fn fetch_disabled_guild_ids(
+        &self,
+        series: Series,
+    ) -> Result<Vec<i64>> {
+        database::fetch_disabled_guild_ids(&self.conn, series).await
+    }
+
+    async fn record_destination_failure(
+        &self,
+        guild_id: i64,
+        series: Series,
+        error: &str,
+    ) -> Result<DestinationHealth> {
+        database::record_destination_failure(
+            &self.conn, guild_id, series, error,
+        )
+        .await
+    }
+
+    async fn disable_destination(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<bool> {
+        database::disable_destination(&self.conn, guild_id, series).await
+    }
+
+    async fn reset_destination_health(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result {
+        database::reset_destination_health(&self.conn, guild_id, series).await
+    }
+}
@@ -0,0 +1,914 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use chrono::{DateTime, Datelike, Utc};
+use f1_bot_types::{
+    Document, DocumentStatus, Event, EventStatus, Image, Series,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use serenity::async_trait;
+
+use super::Storage;
+use crate::{
+    database::timestamp,
+    error::{Error, Result},
+    model::{
+        allow_request::{AllowRequest, AllowRequestStatus},
+        category::DocumentCategory,
+        delivery::{Delivery, DeliveryStatus},
+        guild::{Guild, SeriesSettings, series_key},
+        health::DestinationHealth,
+        thread::Thread,
+    },
+};
+
+/// [`Storage`] that keeps everything in memory, for tests.
+///
+/// Rows are kept as JSON and deserialized on every read, like libsql hands
+/// out fresh values for every query. Events, documents and images only come
+/// in through the `insert_*` methods, the bot itself never writes them.
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: Mutex<Tables>,
+}
+
+#[derive(Default)]
+struct Tables {
+    last_id: i64,
+    events: Vec<Value>,
+    documents: Vec<Value>,
+    images: Vec<Value>,
+    allow_requests: Vec<Value>,
+    guilds: Vec<Value>,
+    series_settings: Vec<(i64, SeriesSettings)>,
+    threads: Vec<Value>,
+    deliveries: Vec<Value>,
+    destination_health: Vec<Value>,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn guild_id(
+        &self,
+        discord_id: &str,
+    ) -> Option<i64> {
+        self.guilds
+            .iter()
+            .find(|f| f["discord_id"] == discord_id)
+            .and_then(|f| f["id"].as_i64())
+    }
+
+    fn load_guilds(
+        &self,
+        filter: impl Fn(&Value) -> bool,
+    ) -> Result<Vec<Guild>> {
+        self.guilds
+            .iter()
+            .filter(|f| filter(f))
+            .map(|row| {
+                let mut guild = from_json::<Guild>(row)?;
+                guild.series_settings = self
+                    .series_settings
+                    .iter()
+                    .filter(|(guild_id, _)| *guild_id == guild.id)
+                    .map(|(_, settings)| settings.clone())
+                    .collect();
+                Ok(guild)
+            })
+            .collect()
+    }
+
+    /// Settings of the series for the guild, added with the defaults if
+    /// there are none yet. `None` if the guild isn't known.
+    fn settings_mut(
+        &mut self,
+        discord_id: &str,
+        series: Series,
+    ) -> Option<&mut SeriesSettings> {
+        let guild_id = self.guild_id(discord_id)?;
+        let position = self.series_settings.iter().position(|(id, f)| {
+            *id == guild_id && series_key(f.series) == series_key(series)
+        });
+        let position = position.unwrap_or_else(|| {
+            self.series_settings.push((
+                guild_id,
+                SeriesSettings {
+                    series,
+                    channel: None,
+                    role: None,
+                    threads: true,
+                    categories: None,
+                },
+            ));
+            self.series_settings.len() - 1
+        });
+        Some(&mut self.series_settings[position].1)
+    }
+
+    fn delivery_mut(
+        &mut self,
+        delivery_id: i64,
+    ) -> Option<&mut Value> {
+        self.deliveries.iter_mut().find(|f| f["id"] == delivery_id)
+    }
+
+    fn health_mut(
+        &mut self,
+        guild_id: i64,
+        series: &Value,
+    ) -> Option<&mut Value> {
+        self.destination_health
+            .iter_mut()
+            .find(|f| f["guild_id"] == guild_id && f["series"] == *series)
+    }
+}
+
+fn to_json(value: impl Serialize) -> Result<Value> {
+    serde_json::to_value(value)
+        .map_err(|e| Error::Serde(serde::de::Error::custom(e)))
+}
+
+fn from_json<T: DeserializeOwned>(value: &Value) -> Result<T> {
+    T::deserialize(value).map_err(|e| Error::Serde(serde::de::Error::custom(e)))
+}
+
+fn now() -> Value {
+    json!(timestamp(Utc::now()))
+}
+
+impl MemoryStorage {
+    fn tables(&self) -> std::sync::MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+
+    pub fn insert_event(
+        &self,
+        event: &Event,
+    ) -> Result {
+        let row = to_json(event)?;
+        self.tables().events.push(row);
+        Ok(())
+    }
+
+    pub fn insert_document(
+        &self,
+        document: &Document,
+    ) -> Result {
+        let row = to_json(document)?;
+        self.tables().documents.push(row);
+        Ok(())
+    }
+
+    pub fn insert_image(
+        &self,
+        document_id: i64,
+        url: &str,
+        page_number: i64,
+    ) {
+        let mut tables = self.tables();
+        let id = tables.next_id();
+        tables.images.push(json!({
+            "id": id,
+            "document_id": document_id,
+            "url": url,
+            "page_number": page_number,
+        }));
+    }
+
+    /// Every delivery ever queued, oldest first.
+    pub fn deliveries(&self) -> Result<Vec<Delivery>> {
+        self.tables().deliveries.iter().map(from_json).collect()
+    }
+
+    pub fn threads(&self) -> Result<Vec<Thread>> {
+        self.tables().threads.iter().map(from_json).collect()
+    }
+
+    pub fn allow_requests(&self) -> Result<Vec<AllowRequest>> {
+        self.tables().allow_requests.iter().map(from_json).collect()
+    }
+
+    fn update_failed_delivery(
+        &self,
+        delivery_id: i64,
+        status: DeliveryStatus,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) {
+        let mut tables = self.tables();
+        let Some(delivery) = tables.delivery_mut(delivery_id) else {
+            return;
+        };
+        let attempts = delivery["attempts"].as_i64().unwrap_or_default();
+        delivery["status"] = json!(status.to_str());
+        delivery["error"] = json!(error);
+        delivery["attempts"] = json!(attempts + 1);
+        delivery["next_attempt_at"] = json!(next_attempt_at.map(timestamp));
+        delivery["updated_at"] = now();
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn fetch_latest_event_by_series(
+        &self,
+        series: Series,
+    ) -> Result<Option<Event>> {
+        let series = to_json(series)?;
+        let tables = self.tables();
+        tables
+            .events
+            .iter()
+            .filter(|f| f["series"] == series)
+            .max_by(|a, b| {
+                a["created_at"].as_str().cmp(&b["created_at"].as_str())
+            })
+            .map(from_json)
+            .transpose()
+    }
+
+    async fn fetch_events_by_status(
+        &self,
+        status: EventStatus,
+    ) -> Result<Vec<Event>> {
+        let status = to_json(status)?;
+        let year = i64::from(Utc::now().year());
+        let tables = self.tables();
+        tables
+            .events
+            .iter()
+            .filter(|f| f["status"] == status && f["year"] == year)
+            .map(from_json)
+            .collect()
+    }
+
+    async fn get_event_by_id(
+        &self,
+        id: u64,
+    ) -> Result<Option<Event>> {
+        let tables = self.tables();
+        tables.events.iter().find(|f| f["id"] == id).map(from_json).transpose()
+    }
+
+    async fn mark_event_done(
+        &self,
+        event_id: i64,
+    ) -> Result {
+        let status = to_json(EventStatus::Posted)?;
+        let mut tables = self.tables();
+        if let Some(event) =
+            tables.events.iter_mut().find(|f| f["id"] == event_id)
+        {
+            event["status"] = status;
+        }
+        Ok(())
+    }
+
+    async fn fetch_allow_request(
+        &self,
+        event_id: u64,
+    ) -> Result<Option<AllowRequest>> {
+        let tables = self.tables();
+        tables
+            .allow_requests
+            .iter()
+            .find(|f| f["event_id"] == event_id)
+            .map(from_json)
+            .transpose()
+    }
+
+    async fn insert_allow_request(
+        &self,
+        event_id: u64,
+    ) -> Result<AllowRequest> {
+        let mut tables = self.tables();
+        let id = tables.next_id();
+        let row = json!({
+            "id": id,
+            "event_id": event_id,
+            "response": AllowRequestStatus::Open.to_str(),
+            "created_at": now(),
+            "approved_by": null,
+            "approved_at": null,
+        });
+        let request = from_json(&row)?;
+        tables.allow_requests.push(row);
+        Ok(request)
+    }
+
+    async fn resolve_allow_request(
+        &self,
+        id: i64,
+        user_id: &str,
+        new_status: AllowRequestStatus,
+    ) -> Result {
+        let event_status = to_json(match new_status {
+            AllowRequestStatus::Allowed => EventStatus::Allowed,
+            AllowRequestStatus::Denied => EventStatus::Denied,
+            _ => EventStatus::NotAllowed,
+        })?;
+        let response = to_json(new_status)?;
+        let mut tables = self.tables();
+        let Some(request) =
+            tables.allow_requests.iter_mut().find(|f| f["id"] == id)
+        else {
+            return Ok(());
+        };
+        request["response"] = response;
+        request["approved_by"] = json!(user_id);
+        request["approved_at"] = now();
+        let event_id = request["event_id"].clone();
+        if let Some(event) =
+            tables.events.iter_mut().find(|f| f["id"] == event_id)
+        {
+            event["status"] = event_status;
+        }
+        Ok(())
+    }
+
+    async fn fetch_guilds_for_series(
+        &self,
+        series: Series,
+    ) -> Result<Vec<Guild>> {
+        let tables = self.tables();
+        let subscribed: HashSet<_> = tables
+            .series_settings
+            .iter()
+            .filter(|(_, settings)| {
+                series_key(settings.series) == series_key(series)
+                    && settings.channel.is_some()
+            })
+            .map(|(guild_id, _)| *guild_id)
+            .collect();
+        tables.load_guilds(|f| {
+            f["left_at"].is_null()
+                && f["id"].as_i64().is_some_and(|id| subscribed.contains(&id))
+        })
+    }
+
+    async fn fetch_guild_by_id(
+        &self,
+        guild_id: i64,
+    ) -> Result<Option<Guild>> {
+        Ok(self.tables().load_guilds(|f| f["id"] == guild_id)?.pop())
+    }
+
+    async fn fetch_guild_by_discord_id(
+        &self,
+        discord_id: &str,
+    ) -> Result<Option<Guild>> {
+        Ok(self.tables().load_guilds(|f| f["discord_id"] == discord_id)?.pop())
+    }
+
+    async fn upsert_guild(
+        &self,
+        discord_id: &str,
+        name: &str,
+        joined_at: DateTime<Utc>,
+    ) -> Result {
+        let mut tables = self.tables();
+        if let Some(guild) =
+            tables.guilds.iter_mut().find(|f| f["discord_id"] == discord_id)
+        {
+            guild["name"] = json!(name);
+            guild["left_at"] = Value::Null;
+            return Ok(());
+        }
+        let id = tables.next_id();
+        tables.guilds.push(json!({
+            "id": id,
+            "discord_id": discord_id,
+            "name": name,
+            "joined_at": timestamp(joined_at),
+            "left_at": null,
+        }));
+        Ok(())
+    }
+
+    async fn rename_guild(
+        &self,
+        discord_id: &str,
+        name: &str,
+    ) -> Result {
+        let mut tables = self.tables();
+        if let Some(guild) =
+            tables.guilds.iter_mut().find(|f| f["discord_id"] == discord_id)
+        {
+            guild["name"] = json!(name);
+        }
+        Ok(())
+    }
+
+    async fn mark_guild_left(
+        &self,
+        discord_id: &str,
+    ) -> Result {
+        let mut tables = self.tables();
+        let Some(guild_id) = tables.guild_id(discord_id) else {
+            return Ok(());
+        };
+        let now = now();
+        for guild in &mut tables.guilds {
+            if guild["id"] == guild_id && guild["left_at"].is_null() {
+                guild["left_at"] = now.clone();
+            }
+        }
+        for delivery in &mut tables.deliveries {
+            if delivery["guild_id"] == guild_id
+                && delivery["status"] == DeliveryStatus::Pending.to_str()
+            {
+                delivery["status"] = json!(DeliveryStatus::Failed.to_str());
+                delivery["error"] = json!("The bot was removed from the guild");
+                delivery["updated_at"] = now.clone();
+            }
+        }
+        Ok(())
+    }
+
+    async fn restore_guild(
+        &self,
+        discord_id: &str,
+    ) -> Result<bool> {
+        let mut tables = self.tables();
+        let Some(guild) = tables
+            .guilds
+            .iter_mut()
+            .find(|f| f["discord_id"] == discord_id && !f["left_at"].is_null())
+        else {
+            return Ok(false);
+        };
+        guild["left_at"] = Value::Null;
+        Ok(true)
+    }
+
+    async fn purge_departed_guilds(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64> {
+        let cutoff = timestamp(cutoff);
+        let mut tables = self.tables();
+        let departed: HashSet<i64> = tables
+            .guilds
+            .iter()
+            .filter(|f| {
+                f["left_at"].as_str().is_some_and(|f| f < cutoff.as_str())
+            })
+            .filter_map(|f| f["id"].as_i64())
+            .collect();
+        let kept = |row: &Value| {
+            row["guild_id"].as_i64().is_none_or(|f| !departed.contains(&f))
+        };
+        tables.deliveries.retain(kept);
+        tables.destination_health.retain(kept);
+        tables.threads.retain(kept);
+        tables.series_settings.retain(|(f, _)| !departed.contains(f));
+        tables.guilds.retain(|f| {
+            f["id"].as_i64().is_none_or(|f| !departed.contains(&f))
+        });
+        Ok(departed.len() as u64)
+    }
+
+    async fn upsert_series_settings(
+        &self,
+        discord_id: &str,
+        series: Series,
+        channel: Option<&str>,
+        role: Option<&str>,
+        threads: bool,
+    ) -> Result<u64> {
+        let mut tables = self.tables();
+        let Some(settings) = tables.settings_mut(discord_id, series) else {
+            return Ok(0);
+        };
+        settings.channel = channel.map(str::to_owned);
+        settings.role = role.map(str::to_owned);
+        settings.threads = threads;
+        Ok(1)
+    }
+
+    async fn update_series_categories(
+        &self,
+        discord_id: &str,
+        series: Series,
+        categories: Option<&[DocumentCategory]>,
+    ) -> Result<u64> {
+        let mut tables = self.tables();
+        let Some(settings) = tables.settings_mut(discord_id, series) else {
+            return Ok(0);
+        };
+        settings.categories = categories.map(<[_]>::to_vec);
+        Ok(1)
+    }
+
+    async fn fetch_thread_for_guild_and_event(
+        &self,
+        guild_id: i64,
+        event_id: i64,
+    ) -> Result<Option<Thread>> {
+        let tables = self.tables();
+        tables
+            .threads
+            .iter()
+            .find(|f| f["guild_id"] == guild_id && f["event_id"] == event_id)
+            .map(from_json)
+            .transpose()
+    }
+
+    async fn insert_new_thread(
+        &self,
+        discord_id: &str,
+        guild_id: i64,
+        event_id: i64,
+        channel_id: &str,
+    ) -> Result<i64> {
+        let mut tables = self.tables();
+        let id = tables.next_id();
+        tables.threads.push(json!({
+            "id": id,
+            "discord_id": discord_id,
+            "channel_id": channel_id,
+            "event_id": event_id,
+            "guild_id": guild_id,
+            "created_at": now(),
+        }));
+        Ok(id)
+    }
+
+    async fn fetch_docs_for_event(
+        &self,
+        event_id: i64,
+    ) -> Result<Vec<Document>> {
+        let ready = to_json(DocumentStatus::ReadyToPost)?;
+        let tables = self.tables();
+        let mut documents: Vec<_> = tables
+            .documents
+            .iter()
+            .filter(|f| f["event_id"] == event_id && f["status"] == ready)
+            .collect();
+        documents.sort_by(|a, b| {
+            b["created_at"].as_str().cmp(&a["created_at"].as_str())
+        });
+        documents.into_iter().map(from_json).collect()
+    }
+
+    async fn fetch_document_by_id(
+        &self,
+        document_id: i64,
+    ) -> Result<Option<Document>> {
+        let tables = self.tables();
+        tables
+            .documents
+            .iter()
+            .find(|f| f["id"] == document_id)
+            .map(from_json)
+            .transpose()
+    }
+
+    async fn fetch_document_category(
+        &self,
+        document_id: i64,
+    ) -> Result<Option<DocumentCategory>> {
+        let tables = self.tables();
+        Ok(tables
+            .documents
+            .iter()
+            .find(|f| f["id"] == document_id)
+            .and_then(|f| f["category"].as_str())
+            .and_then(DocumentCategory::from_key))
+    }
+
+    async fn set_document_category(
+        &self,
+        document_id: i64,
+        category: DocumentCategory,
+    ) -> Result {
+        let mut tables = self.tables();
+        if let Some(document) =
+            tables.documents.iter_mut().find(|f| f["id"] == document_id)
+        {
+            document["category"] = json!(category.key());
+        }
+        Ok(())
+    }
+
+    async fn mark_doc_done(
+        &self,
+        document_id: i64,
+    ) -> Result {
+        let status = to_json(DocumentStatus::Posted)?;
+        let mut tables = self.tables();
+        if let Some(document) =
+            tables.documents.iter_mut().find(|f| f["id"] == document_id)
+        {
+            document["status"] = status;
+        }
+        Ok(())
+    }
+
+    async fn fetch_images_for_document(
+        &self,
+        document_id: i64,
+    ) -> Result<Vec<Image>> {
+        let tables = self.tables();
+        let mut images: Vec<_> = tables
+            .images
+            .iter()
+            .filter(|f| f["document_id"] == document_id)
+            .collect();
+        images.sort_by_key(|f| f["page_number"].as_i64());
+        images.into_iter().take(4).map(from_json).collect()
+    }
+
+    async fn insert_pending_delivery(
+        &self,
+        document_id: i64,
+        guild_id: i64,
+        channel_id: &str,
+        role_id: Option<&str>,
+    ) -> Result {
+        let mut tables = self.tables();
+        if tables.deliveries.iter().any(|f| {
+            f["document_id"] == document_id
+                && f["guild_id"] == guild_id
+                && f["channel_id"] == channel_id
+        }) {
+            return Ok(());
+        }
+        let id = tables.next_id();
+        tables.deliveries.push(json!({
+            "id": id,
+            "document_id": document_id,
+            "guild_id": guild_id,
+            "channel_id": channel_id,
+            "role_id": role_id,
+            "status": DeliveryStatus::Pending.to_str(),
+            "message_id": null,
+            "error": null,
+            "attempts": 0,
+            "next_attempt_at": null,
+            "created_at": now(),
+            "updated_at": now(),
+        }));
+        Ok(())
+    }
+
+    async fn fetch_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Delivery>> {
+        let now = timestamp(now);
+        let pending = DeliveryStatus::Pending.to_str();
+        let tables = self.tables();
+        let waiting = |f: &Value| {
+            f["next_attempt_at"].as_str().is_some_and(|f| f > now.as_str())
+        };
+        let mut due: Vec<_> = tables
+            .deliveries
+            .iter()
+            .filter(|d| d["status"] == pending && !waiting(d))
+            .filter(|d| {
+                !tables.deliveries.iter().any(|w| {
+                    w["channel_id"] == d["channel_id"]
+                        && w["status"] == pending
+                        && waiting(w)
+                        && w["id"].as_i64() < d["id"].as_i64()
+                })
+            })
+            .collect();
+        due.sort_by_key(|f| (f["document_id"].as_i64(), f["id"].as_i64()));
+        due.into_iter().map(from_json).collect()
+    }
+
+    async fn fetch_next_attempt_at(&self) -> Result<Option<DateTime<Utc>>> {
+        let tables = self.tables();
+        tables
+            .deliveries
+            .iter()
+            .filter(|f| f["status"] == DeliveryStatus::Pending.to_str())
+            .filter_map(|f| f["next_attempt_at"].as_str())
+            .min()
+            .map(|f| from_json(&json!(f)))
+            .transpose()
+    }
+
+    async fn fetch_last_sent_at(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let series = to_json(series)?;
+        let tables = self.tables();
+        let event_of = |document_id: &Value| {
+            let document =
+                tables.documents.iter().find(|f| f["id"] == *document_id)?;
+            tables.events.iter().find(|f| f["id"] == document["event_id"])
+        };
+        tables
+            .deliveries
+            .iter()
+            .filter(|f| {
+                f["guild_id"] == guild_id
+                    && f["status"] == DeliveryStatus::Sent.to_str()
+                    && event_of(&f["document_id"])
+                        .is_some_and(|f| f["series"] == series)
+            })
+            .filter_map(|f| f["updated_at"].as_str())
+            .max()
+            .map(|f| from_json(&json!(f)))
+            .transpose()
+    }
+
+    async fn mark_delivery_sent(
+        &self,
+        delivery_id: i64,
+        message_id: &str,
+    ) -> Result {
+        let mut tables = self.tables();
+        if let Some(delivery) = tables.delivery_mut(delivery_id) {
+            delivery["status"] = json!(DeliveryStatus::Sent.to_str());
+            delivery["message_id"] = json!(message_id);
+            delivery["error"] = Value::Null;
+            delivery["updated_at"] = now();
+        }
+        Ok(())
+    }
+
+    async fn mark_delivery_failed(
+        &self,
+        delivery_id: i64,
+        error: &str,
+    ) -> Result {
+        self.update_failed_delivery(
+            delivery_id,
+            DeliveryStatus::Failed,
+            error,
+            None,
+        );
+        Ok(())
+    }
+
+    async fn mark_delivery_dead(
+        &self,
+        delivery_id: i64,
+        error: &str,
+    ) -> Result {
+        self.update_failed_delivery(
+            delivery_id,
+            DeliveryStatus::DeadLetter,
+            error,
+            None,
+        );
+        Ok(())
+    }
+
+    async fn schedule_delivery_retry(
+        &self,
+        delivery_id: i64,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result {
+        self.update_failed_delivery(
+            delivery_id,
+            DeliveryStatus::Pending,
+            error,
+            Some(next_attempt_at),
+        );
+        Ok(())
+    }
+
+    async fn has_open_deliveries(
+        &self,
+        document_id: i64,
+    ) -> Result<bool> {
+        let tables = self.tables();
+        Ok(tables.deliveries.iter().any(|f| {
+            f["document_id"] == document_id
+                && f["status"] == DeliveryStatus::Pending.to_str()
+        }))
+    }
+
+    async fn fetch_dead_letters(
+        &self,
+        limit: u32,
+    ) -> Result<Vec<Delivery>> {
+        let tables = self.tables();
+        let mut dead_letters: Vec<_> = tables
+            .deliveries
+            .iter()
+            .filter(|f| f["status"] == DeliveryStatus::DeadLetter.to_str())
+            .collect();
+        dead_letters.sort_by(|a, b| {
+            b["updated_at"].as_str().cmp(&a["updated_at"].as_str())
+        });
+        dead_letters.into_iter().take(limit as usize).map(from_json).collect()
+    }
+
+    async fn replay_dead_letters(
+        &self,
+        delivery_id: Option<i64>,
+    ) -> Result<u64> {
+        let mut tables = self.tables();
+        let mut replayed = 0;
+        for delivery in &mut tables.deliveries {
+            if delivery["status"] != DeliveryStatus::DeadLetter.to_str()
+                || delivery_id.is_some_and(|id| delivery["id"] != id)
+            {
+                continue;
+            }
+            delivery["status"] = json!(DeliveryStatus::Pending.to_str());
+            delivery["attempts"] = json!(0);
+            delivery["next_attempt_at"] = Value::Null;
+            delivery["updated_at"] = now();
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+
+    async fn fetch_destination_health(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<Option<DestinationHealth>> {
+        let series = json!(series_key(series));
+        let mut tables = self.tables();
+        tables.health_mut(guild_id, &series).map(|f| from_json(f)).transpose()
+    }
+
+    async fn fetch_disabled_guild_ids(
+        &self,
+        series: Series,
+    ) -> Result<Vec<i64>> {
+        let series = json!(series_key(series));
+        let tables = self.tables();
+        Ok(tables
+            .destination_health
+            .iter()
+            .filter(|f| f["series"] == series && !f["disabled_at"].is_null())
+            .filter_map(|f| f["guild_id"].as_i64())
+            .collect())
+    }
+
+    async fn record_destination_failure(
+        &self,
+        guild_id: i64,
+        series: Series,
+        error: &str,
+    ) -> Result<DestinationHealth> {
+        let series = json!(series_key(series));
+        let mut tables = self.tables();
+        if tables.health_mut(guild_id, &series).is_none() {
+            tables.destination_health.push(json!({
+                "guild_id": guild_id,
+                "series": series,
+                "consecutive_failures": 0,
+                "last_error": null,
+                "disabled_at": null,
+                "updated_at": now(),
+            }));
+        }
+        let health = tables.health_mut(guild_id, &series).unwrap();
+        let failures =
+            health["consecutive_failures"].as_i64().unwrap_or_default();
+        health["consecutive_failures"] = json!(failures + 1);
+        health["last_error"] = json!(error);
+        health["updated_at"] = now();
+        from_json(health)
+    }
+
+    async fn disable_destination(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<bool> {
+        let series = json!(series_key(series));
+        let mut tables = self.tables();
+        let Some(health) = tables.health_mut(guild_id, &series) else {
+            return Ok(false);
+        };
+        if !health["disabled_at"].is_null() {
+            return Ok(false);
+        }
+        health["disabled_at"] = now();
+        Ok(true)
+    }
+
+    async fn reset_destination_health(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result {
+        let series = json!(series_key(series));
+        let mut tables = self.tables();
+        if let Some(health) = tables.health_mut(guild_id, &series) {
+            health["consecutive_failures"] = json!(0);
+            health["last_error"] = Value::Null;
+            health["disabled_at"] = Value::Null;
+            health["updated_at"] = now();
+        }
+        Ok(())
+    }
+}
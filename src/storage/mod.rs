@@ -0,0 +1,262 @@
+use chrono::{DateTime, Utc};
+use f1_bot_types::{Document, Event, EventStatus, Image, Series};
+use serenity::async_trait;
+
+use crate::{
+    error::Result,
+    model::{
+        allow_request::{AllowRequest, AllowRequestStatus},
+        category::DocumentCategory,
+        delivery::Delivery,
+        guild::Guild,
+        health::DestinationHealth,
+        thread::Thread,
+    },
+};
+
+mod libsql;
+#[cfg(test)]
+pub mod memory;
+
+pub use self::libsql::LibsqlStorage;
+
+/// Everything the runner and the commands read from or write to the
+/// database. See the functions of the same name in `database.rs` for what
+/// each of them does.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn fetch_latest_event_by_series(
+        &self,
+        series: Series,
+    ) -> Result<Option<Event>>;
+
+    /// Events of the current year with the status.
+    async fn fetch_events_by_status(
+        &self,
+        status: EventStatus,
+    ) -> Result<Vec<Event>>;
+
+    async fn get_event_by_id(
+        &self,
+        id: u64,
+    ) -> Result<Option<Event>>;
+
+    async fn mark_event_done(
+        &self,
+        event_id: i64,
+    ) -> Result;
+
+    async fn fetch_allow_request(
+        &self,
+        event_id: u64,
+    ) -> Result<Option<AllowRequest>>;
+
+    async fn insert_allow_request(
+        &self,
+        event_id: u64,
+    ) -> Result<AllowRequest>;
+
+    async fn resolve_allow_request(
+        &self,
+        id: i64,
+        user_id: &str,
+        new_status: AllowRequestStatus,
+    ) -> Result;
+
+    async fn fetch_guilds_for_series(
+        &self,
+        series: Series,
+    ) -> Result<Vec<Guild>>;
+
+    async fn fetch_guild_by_id(
+        &self,
+        guild_id: i64,
+    ) -> Result<Option<Guild>>;
+
+    async fn fetch_guild_by_discord_id(
+        &self,
+        discord_id: &str,
+    ) -> Result<Option<Guild>>;
+
+    async fn upsert_guild(
+        &self,
+        discord_id: &str,
+        name: &str,
+        joined_at: DateTime<Utc>,
+    ) -> Result;
+
+    async fn rename_guild(
+        &self,
+        discord_id: &str,
+        name: &str,
+    ) -> Result;
+
+    async fn mark_guild_left(
+        &self,
+        discord_id: &str,
+    ) -> Result;
+
+    async fn restore_guild(
+        &self,
+        discord_id: &str,
+    ) -> Result<bool>;
+
+    async fn purge_departed_guilds(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64>;
+
+    async fn upsert_series_settings(
+        &self,
+        discord_id: &str,
+        series: Series,
+        channel: Option<&str>,
+        role: Option<&str>,
+        threads: bool,
+    ) -> Result<u64>;
+
+    async fn update_series_categories(
+        &self,
+        discord_id: &str,
+        series: Series,
+        categories: Option<&[DocumentCategory]>,
+    ) -> Result<u64>;
+
+    async fn fetch_thread_for_guild_and_event(
+        &self,
+        guild_id: i64,
+        event_id: i64,
+    ) -> Result<Option<Thread>>;
+
+    async fn insert_new_thread(
+        &self,
+        discord_id: &str,
+        guild_id: i64,
+        event_id: i64,
+        channel_id: &str,
+    ) -> Result<i64>;
+
+    /// Documents of the event that are ready to be posted, newest first.
+    async fn fetch_docs_for_event(
+        &self,
+        event_id: i64,
+    ) -> Result<Vec<Document>>;
+
+    async fn fetch_document_by_id(
+        &self,
+        document_id: i64,
+    ) -> Result<Option<Document>>;
+
+    async fn fetch_document_category(
+        &self,
+        document_id: i64,
+    ) -> Result<Option<DocumentCategory>>;
+
+    async fn set_document_category(
+        &self,
+        document_id: i64,
+        category: DocumentCategory,
+    ) -> Result;
+
+    async fn mark_doc_done(
+        &self,
+        document_id: i64,
+    ) -> Result;
+
+    /// The first four pages of the document.
+    async fn fetch_images_for_document(
+        &self,
+        document_id: i64,
+    ) -> Result<Vec<Image>>;
+
+    async fn insert_pending_delivery(
+        &self,
+        document_id: i64,
+        guild_id: i64,
+        channel_id: &str,
+        role_id: Option<&str>,
+    ) -> Result;
+
+    async fn fetch_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Delivery>>;
+
+    async fn fetch_next_attempt_at(&self) -> Result<Option<DateTime<Utc>>>;
+
+    async fn fetch_last_sent_at(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<Option<DateTime<Utc>>>;
+
+    async fn mark_delivery_sent(
+        &self,
+        delivery_id: i64,
+        message_id: &str,
+    ) -> Result;
+
+    async fn mark_delivery_failed(
+        &self,
+        delivery_id: i64,
+        error: &str,
+    ) -> Result;
+
+    async fn mark_delivery_dead(
+        &self,
+        delivery_id: i64,
+        error: &str,
+    ) -> Result;
+
+    async fn schedule_delivery_retry(
+        &self,
+        delivery_id: i64,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result;
+
+    async fn has_open_deliveries(
+        &self,
+        document_id: i64,
+    ) -> Result<bool>;
+
+    async fn fetch_dead_letters(
+        &self,
+        limit: u32,
+    ) -> Result<Vec<Delivery>>;
+
+    async fn replay_dead_letters(
+        &self,
+        delivery_id: Option<i64>,
+    ) -> Result<u64>;
+
+    async fn fetch_destination_health(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<Option<DestinationHealth>>;
+
+    async fn fetch_disabled_guild_ids(
+        &self,
+        series: Series,
+    ) -> Result<Vec<i64>>;
+
+    async fn record_destination_failure(
+        &self,
+        guild_id: i64,
+        series: Series,
+        error: &str,
+    ) -> Result<DestinationHealth>;
+
+    async fn disable_destination(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result<bool>;
+
+    async fn reset_destination_health(
+        &self,
+        guild_id: i64,
+        series: Series,
+    ) -> Result;
+}
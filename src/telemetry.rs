@@ -0,0 +1,103 @@
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    trace::{Sampler, SdkTracerProvider},
+};
+use sentry::ClientInitGuard;
+use tracing::{Level, error, level_filters::LevelFilter};
+use tracing_subscriber::{
+    Layer, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+use crate::{config::TelemetryConfig, metrics::MetricsLayer};
+
+/// Keeps the telemetry backends alive, [`Telemetry::shutdown`] flushes
+/// whatever is still buffered.
+pub struct Telemetry {
+    sentry: Option<ClientInitGuard>,
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+/// Sets up logging plus whichever of Sentry and the OTLP exporter are
+/// configured. Sentry calls elsewhere are no-ops while it's disabled.
+pub fn init(config: &TelemetryConfig) -> Telemetry {
+    let dsn =
+        config.sentry_dsn.clone().or_else(|| std::env::var("SENTRY_DSN").ok());
+    let sentry = dsn.map(|dsn| {
+        let guard = sentry::init((
+            dsn,
+            sentry::ClientOptions {
+                release: sentry::release_name!(),
+                traces_sample_rate: config.traces_sample_rate,
+                ..Default::default()
+            },
+        ));
+        sentry::start_session();
+        guard
+    });
+
+    let (tracer_provider, otlp_error) = match &config.otlp_endpoint {
+        None => (None, None),
+        Some(endpoint) => match tracer_provider(config, endpoint) {
+            Ok(provider) => (Some(provider), None),
+            Err(why) => (None, Some(why)),
+        },
+    };
+    let otel_layer = tracer_provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(config.service_name.clone()))
+            .with_filter(LevelFilter::from_level(Level::INFO))
+    });
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_filter(LevelFilter::from_level(Level::INFO)),
+        )
+        .with(otel_layer)
+        .with(MetricsLayer)
+        .init();
+    // Only reportable once the subscriber exists.
+    if let Some(why) = otlp_error {
+        error!("Error setting up the OTLP exporter: {why}");
+    }
+
+    Telemetry {
+        sentry,
+        tracer_provider,
+    }
+}
+
+fn tracer_provider(
+    config: &TelemetryConfig,
+    endpoint: &str,
+) -> Result<SdkTracerProvider, opentelemetry_otlp::ExporterBuildError> {
+    let exporter =
+        SpanExporter::builder().with_http().with_endpoint(endpoint).build()?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(
+            Sampler::TraceIdRatioBased(config.traces_sample_rate.into()),
+        )))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.tracer_provider {
+            if let Err(why) = provider.shutdown() {
+                error!("Error flushing traces: {why}");
+            }
+        }
+        if let Some(guard) = self.sentry {
+            sentry::end_session();
+            drop(guard);
+        }
+    }
+}